@@ -0,0 +1,34 @@
+#![feature(test)]
+
+extern crate doogie;
+extern crate test;
+
+use doogie::{parse_document, Parser};
+use test::Bencher;
+
+const MESSAGES: [&str; 4] = [
+    "Hello *world*\n",
+    "# Title\n\n* item\n* another item\n",
+    "A [link](https://example.com) and `code`\n",
+    "> quoted\n",
+];
+
+#[bench]
+fn bench_parse_document(b: &mut Bencher) {
+    b.iter(|| {
+        for message in &MESSAGES {
+            test::black_box(parse_document(message));
+        }
+    });
+}
+
+#[bench]
+fn bench_parser_reuse(b: &mut Bencher) {
+    let mut parser = Parser::new();
+    b.iter(|| {
+        for message in &MESSAGES {
+            parser.feed(message);
+            test::black_box(parser.finish_and_reset());
+        }
+    });
+}
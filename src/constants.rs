@@ -3,6 +3,7 @@ extern crate try_from;
 use self::try_from::TryFrom;
 use super::{DoogieError, DoogieResult};
 use std::collections::HashSet;
+use std::ops::{BitOr, BitOrAssign};
 
 /// Each NodeIterator step is parameterized by one of these event.
 #[derive(PartialEq, Debug)]
@@ -43,6 +44,11 @@ impl TryFrom<u32> for IterEventType {
 
 /// Each Node in the libcmark document AST possesses a type attribute that corresponds to its
 /// equivalent CommonMark semantic element.
+///
+/// These are the types of the reference libcmark, which implements CommonMark without any
+/// extensions. Extension syntax such as footnotes, tables and strikethrough has no node types. A
+/// footnote definition like `[^1]: A note` is parsed as a link reference definition, for example,
+/// and each `[^1]` referring to it as a `Link`.
 #[derive(PartialEq, Debug, Clone, Eq, Hash)]
 pub enum NodeType {
     CMarkNodeNone,
@@ -96,10 +102,64 @@ impl From<NodeType> for u32 {
     }
 }
 
-impl TryFrom<u32> for NodeType {
-    type Err = DoogieError;
+impl NodeType {
+    /// Returns every `NodeType` of a `Node`, in the order of libcmark's `cmark_node_type` enum
+    ///
+    /// `CMarkNodeNone` is not included since no `Node` has that type.
+    pub fn all() -> &'static [NodeType] {
+        static ALL: &[NodeType] = &[
+            NodeType::CMarkNodeDocument,
+            NodeType::CMarkNodeBlockQuote,
+            NodeType::CMarkNodeList,
+            NodeType::CMarkNodeItem,
+            NodeType::CMarkNodeCodeBlock,
+            NodeType::CMarkNodeHtmlBlock,
+            NodeType::CMarkNodeCustomBlock,
+            NodeType::CMarkNodeParagraph,
+            NodeType::CMarkNodeHeading,
+            NodeType::CMarkNodeThematicBreak,
+            NodeType::CMarkNodeText,
+            NodeType::CMarkNodeSoftbreak,
+            NodeType::CMarkNodeLinebreak,
+            NodeType::CMarkNodeCode,
+            NodeType::CMarkNodeHtmlInline,
+            NodeType::CMarkNodeCustomInline,
+            NodeType::CMarkNodeEmph,
+            NodeType::CMarkNodeStrong,
+            NodeType::CMarkNodeLink,
+            NodeType::CMarkNodeImage,
+        ];
+
+        ALL
+    }
 
-    fn try_from(original: u32) -> DoogieResult<Self> {
+    /// Returns whether `Node`s of this type may have children
+    ///
+    /// This holds exactly for the types whose set of valid child types, such as
+    /// `DOCUMENT_CHILDREN`, is not empty.
+    pub fn is_container(&self) -> bool {
+        match *self {
+            NodeType::CMarkNodeDocument
+            | NodeType::CMarkNodeBlockQuote
+            | NodeType::CMarkNodeList
+            | NodeType::CMarkNodeItem
+            | NodeType::CMarkNodeCustomBlock
+            | NodeType::CMarkNodeParagraph
+            | NodeType::CMarkNodeHeading
+            | NodeType::CMarkNodeCustomInline
+            | NodeType::CMarkNodeEmph
+            | NodeType::CMarkNodeStrong
+            | NodeType::CMarkNodeLink
+            | NodeType::CMarkNodeImage => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the `NodeType` corresponding to a libcmark node type number
+    ///
+    /// The numbers are those of libcmark's `cmark_node_type` enum, which `u32::from` produces. A
+    /// `DoogieError::BadEnum` error is returned for numbers outside of that enum.
+    pub fn from_u32(original: u32) -> DoogieResult<Self> {
         match original {
             0 => Ok(NodeType::CMarkNodeNone),
             1 => Ok(NodeType::CMarkNodeDocument),
@@ -127,6 +187,14 @@ impl TryFrom<u32> for NodeType {
     }
 }
 
+impl TryFrom<u32> for NodeType {
+    type Err = DoogieError;
+
+    fn try_from(original: u32) -> DoogieResult<Self> {
+        NodeType::from_u32(original)
+    }
+}
+
 /// List elements have one of these types associated with them
 #[derive(PartialEq)]
 pub enum ListType {
@@ -189,29 +257,163 @@ impl TryFrom<u32> for DelimType {
     }
 }
 
+/// The syntax in which a Heading was written in the source
+#[derive(PartialEq, Debug)]
+pub enum HeadingStyle {
+    /// Introduced by a run of `#` characters
+    Atx,
+    /// Underlined by a line of `=` or `-` characters
+    Setext,
+}
+
+/// A set of libcmark `CMARK_OPT_*` options for parsing and rendering
+///
+/// Options are combined with `|`, e.g. `DoogieOptions::SOURCEPOS | DoogieOptions::SMART`. The
+/// default value is the empty set, which matches the behavior of `parse_document` and the plain
+/// render methods.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct DoogieOptions {
+    bits: u32,
+}
+
+impl DoogieOptions {
+    /// Include a `data-sourcepos` attribute on block elements when rendering HTML and a
+    /// `sourcepos` attribute when rendering XML
+    pub const SOURCEPOS: DoogieOptions = DoogieOptions { bits: 1 << 1 };
+    /// Render soft breaks as hard line breaks
+    pub const HARDBREAKS: DoogieOptions = DoogieOptions { bits: 1 << 2 };
+    /// Suppress raw HTML and unsafe links when rendering HTML, for libcmark versions that keep
+    /// them by default
+    pub const SAFE: DoogieOptions = DoogieOptions { bits: 1 << 3 };
+    /// Render soft breaks as spaces
+    pub const NOBREAKS: DoogieOptions = DoogieOptions { bits: 1 << 4 };
+    /// Consolidate adjacent text nodes while parsing
+    pub const NORMALIZE: DoogieOptions = DoogieOptions { bits: 1 << 8 };
+    /// Replace invalid UTF-8 in the input with U+FFFD REPLACEMENT CHARACTER while parsing
+    pub const VALIDATE_UTF8: DoogieOptions = DoogieOptions { bits: 1 << 9 };
+    /// Convert straight quotes to curly quotes, `---` to em dashes and `--` to en dashes while
+    /// parsing
+    pub const SMART: DoogieOptions = DoogieOptions { bits: 1 << 10 };
+    /// Keep raw HTML and unsafe links when rendering HTML, for libcmark versions that omit them
+    /// by default
+    pub const UNSAFE: DoogieOptions = DoogieOptions { bits: 1 << 17 };
+
+    /// Returns the empty set of options
+    pub fn empty() -> Self {
+        DoogieOptions { bits: 0 }
+    }
+
+    /// Returns the `CMARK_OPT_*` bits of the options as passed to libcmark
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns whether all of the given options are set
+    pub fn contains(&self, other: DoogieOptions) -> bool {
+        self.bits & other.bits == other.bits
+    }
+}
+
+impl BitOr for DoogieOptions {
+    type Output = DoogieOptions;
+
+    fn bitor(self, other: DoogieOptions) -> DoogieOptions {
+        DoogieOptions {
+            bits: self.bits | other.bits,
+        }
+    }
+}
+
+impl BitOrAssign for DoogieOptions {
+    fn bitor_assign(&mut self, other: DoogieOptions) {
+        self.bits |= other.bits;
+    }
+}
+
+/// Named HTML character references recognized by `decode_entities`, without their `&` and `;`
+///
+/// This covers the references commonly found in prose rather than the full HTML table.
+pub static HTML_ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+    ("nbsp", "\u{a0}"),
+    ("iexcl", "¡"),
+    ("cent", "¢"),
+    ("pound", "£"),
+    ("yen", "¥"),
+    ("euro", "€"),
+    ("sect", "§"),
+    ("copy", "©"),
+    ("reg", "®"),
+    ("trade", "™"),
+    ("deg", "°"),
+    ("plusmn", "±"),
+    ("times", "×"),
+    ("divide", "÷"),
+    ("micro", "µ"),
+    ("para", "¶"),
+    ("middot", "·"),
+    ("frac14", "¼"),
+    ("frac12", "½"),
+    ("frac34", "¾"),
+    ("iquest", "¿"),
+    ("laquo", "«"),
+    ("raquo", "»"),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("sbquo", "‚"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("bdquo", "„"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("hellip", "…"),
+    ("bull", "•"),
+    ("prime", "′"),
+    ("larr", "←"),
+    ("rarr", "→"),
+    ("uarr", "↑"),
+    ("darr", "↓"),
+    ("harr", "↔"),
+    ("ne", "≠"),
+    ("le", "≤"),
+    ("ge", "≥"),
+    ("infin", "∞"),
+    ("shy", "\u{ad}"),
+    ("ensp", "\u{2002}"),
+    ("emsp", "\u{2003}"),
+    ("thinsp", "\u{2009}"),
+    ("zwnj", "\u{200c}"),
+    ("zwj", "\u{200d}"),
+];
+
+/// Valid child types of Document elements, as a slice
+pub static DOCUMENT_CHILD_TYPES: &[NodeType] = &[
+    NodeType::CMarkNodeParagraph,
+    NodeType::CMarkNodeHeading,
+    NodeType::CMarkNodeThematicBreak,
+    NodeType::CMarkNodeCodeBlock,
+    NodeType::CMarkNodeHtmlBlock,
+    NodeType::CMarkNodeCustomBlock,
+    NodeType::CMarkNodeList,
+    NodeType::CMarkNodeBlockQuote,
+];
+
 /// Valid child types of Document elements
 lazy_static! {
-    pub static ref DOCUMENT_CHILDREN: HashSet<NodeType> = {
-        let mut children = HashSet::new();
-        children.insert(NodeType::CMarkNodeParagraph);
-        children.insert(NodeType::CMarkNodeHeading);
-        children.insert(NodeType::CMarkNodeThematicBreak);
-        children.insert(NodeType::CMarkNodeCodeBlock);
-        children.insert(NodeType::CMarkNodeHtmlBlock);
-        children.insert(NodeType::CMarkNodeCustomBlock);
-        children.insert(NodeType::CMarkNodeList);
-        children.insert(NodeType::CMarkNodeBlockQuote);
-        children
-    };
+    pub static ref DOCUMENT_CHILDREN: HashSet<NodeType> =
+        DOCUMENT_CHILD_TYPES.iter().cloned().collect();
 }
 
+/// Valid child types of List elements, as a slice
+pub static LIST_CHILD_TYPES: &[NodeType] = &[NodeType::CMarkNodeItem];
+
 /// Valid child types of List elements
 lazy_static! {
-    pub static ref LIST_CHILDREN: HashSet<NodeType> = {
-        let mut children = HashSet::new();
-        children.insert(NodeType::CMarkNodeItem);
-        children
-    };
+    pub static ref LIST_CHILDREN: HashSet<NodeType> = LIST_CHILD_TYPES.iter().cloned().collect();
 }
 
 /// Valid child types of List Item elements
@@ -234,49 +436,53 @@ lazy_static! {
     pub static ref HTML_BLOCK_CHILDREN: HashSet<NodeType> = { HashSet::new() };
 }
 
+/// Valid child types of Custom Block elements, as a slice
+pub static CUSTOM_BLOCK_CHILD_TYPES: &[NodeType] = &[
+    NodeType::CMarkNodeBlockQuote,
+    NodeType::CMarkNodeList,
+    NodeType::CMarkNodeItem,
+    NodeType::CMarkNodeCodeBlock,
+    NodeType::CMarkNodeHtmlBlock,
+    NodeType::CMarkNodeCustomBlock,
+    NodeType::CMarkNodeParagraph,
+    NodeType::CMarkNodeHeading,
+    NodeType::CMarkNodeThematicBreak,
+    NodeType::CMarkNodeText,
+    NodeType::CMarkNodeSoftbreak,
+    NodeType::CMarkNodeLinebreak,
+    NodeType::CMarkNodeCode,
+    NodeType::CMarkNodeHtmlInline,
+    NodeType::CMarkNodeCustomInline,
+    NodeType::CMarkNodeEmph,
+    NodeType::CMarkNodeStrong,
+    NodeType::CMarkNodeLink,
+    NodeType::CMarkNodeImage,
+];
+
 /// Valid child types of Custom Block elements
 lazy_static! {
-    pub static ref CUSTOM_BLOCK_CHILDREN: HashSet<NodeType> = {
-        let mut children = HashSet::new();
-        children.insert(NodeType::CMarkNodeBlockQuote);
-        children.insert(NodeType::CMarkNodeList);
-        children.insert(NodeType::CMarkNodeItem);
-        children.insert(NodeType::CMarkNodeCodeBlock);
-        children.insert(NodeType::CMarkNodeHtmlBlock);
-        children.insert(NodeType::CMarkNodeCustomBlock);
-        children.insert(NodeType::CMarkNodeParagraph);
-        children.insert(NodeType::CMarkNodeHeading);
-        children.insert(NodeType::CMarkNodeThematicBreak);
-        children.insert(NodeType::CMarkNodeText);
-        children.insert(NodeType::CMarkNodeSoftbreak);
-        children.insert(NodeType::CMarkNodeLinebreak);
-        children.insert(NodeType::CMarkNodeCode);
-        children.insert(NodeType::CMarkNodeHtmlInline);
-        children.insert(NodeType::CMarkNodeCustomInline);
-        children.insert(NodeType::CMarkNodeEmph);
-        children.insert(NodeType::CMarkNodeStrong);
-        children.insert(NodeType::CMarkNodeLink);
-        children.insert(NodeType::CMarkNodeImage);
-        children
-    };
-}
+    pub static ref CUSTOM_BLOCK_CHILDREN: HashSet<NodeType> =
+        CUSTOM_BLOCK_CHILD_TYPES.iter().cloned().collect();
+}
+
+/// Valid child types of Paragraph elements, as a slice
+pub static PARAGRAPH_CHILD_TYPES: &[NodeType] = &[
+    NodeType::CMarkNodeText,
+    NodeType::CMarkNodeEmph,
+    NodeType::CMarkNodeCode,
+    NodeType::CMarkNodeLink,
+    NodeType::CMarkNodeImage,
+    NodeType::CMarkNodeSoftbreak,
+    NodeType::CMarkNodeLinebreak,
+    NodeType::CMarkNodeHtmlInline,
+    NodeType::CMarkNodeCustomInline,
+    NodeType::CMarkNodeStrong,
+];
 
 /// Valid child types of Paragraph elements
 lazy_static! {
-    pub static ref PARAGRAPH_CHILDREN: HashSet<NodeType> = {
-        let mut children = HashSet::new();
-        children.insert(NodeType::CMarkNodeText);
-        children.insert(NodeType::CMarkNodeEmph);
-        children.insert(NodeType::CMarkNodeCode);
-        children.insert(NodeType::CMarkNodeLink);
-        children.insert(NodeType::CMarkNodeImage);
-        children.insert(NodeType::CMarkNodeSoftbreak);
-        children.insert(NodeType::CMarkNodeLinebreak);
-        children.insert(NodeType::CMarkNodeHtmlInline);
-        children.insert(NodeType::CMarkNodeCustomInline);
-        children.insert(NodeType::CMarkNodeStrong);
-        children
-    };
+    pub static ref PARAGRAPH_CHILDREN: HashSet<NodeType> =
+        PARAGRAPH_CHILD_TYPES.iter().cloned().collect();
 }
 
 /// Valid child types of HEADING elements
@@ -0,0 +1,158 @@
+//! Structural comparison of document trees
+
+use super::{DoogieResult, Node, NodeResource};
+
+/// A difference between two document trees, as returned by `diff`
+///
+/// Paths are the child indices leading from the root of a tree to a `Node`, as accepted by
+/// `Node::at_path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeEdit {
+    /// The `Node` at `path` in the new tree, along with its subtree, is not in the old tree
+    Insert { path: Vec<usize> },
+    /// The `Node` at `path` in the old tree, along with its subtree, is not in the new tree
+    Remove { path: Vec<usize> },
+    /// The `Node` at `old_path` in the old tree has the same type as the `Node` at `new_path` in
+    /// the new tree but different attributes or content
+    Change {
+        old_path: Vec<usize>,
+        new_path: Vec<usize>,
+    },
+}
+
+/// Returns the edits that turn the tree rooted at `a` into the tree rooted at `b`
+///
+/// The children of each pair of corresponding `Node`s are matched with a longest common
+/// subsequence over identical subtrees. The unmatched children in between are paired up in order
+/// while their types agree and compared recursively; the others are reported as insertions and
+/// removals. Source positions are ignored. The edits are listed in document order.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::{diff, parse_document, TreeEdit};
+///
+/// let old = parse_document("One\n\nTwo\n");
+/// let new = parse_document("One\n\nTwo\n\nThree\n");
+///
+/// assert_eq!(diff(&old, &new).unwrap(), vec![TreeEdit::Insert { path: vec![2] }]);
+/// ```
+pub fn diff(a: &Node, b: &Node) -> DoogieResult<Vec<TreeEdit>> {
+    let mut edits = Vec::new();
+    diff_nodes(a, b, &mut vec![], &mut vec![], &mut edits)?;
+
+    Ok(edits)
+}
+
+/// Compares two `Node`s of the same type and their children
+fn diff_nodes(
+    a: &Node,
+    b: &Node,
+    a_path: &mut Vec<usize>,
+    b_path: &mut Vec<usize>,
+    edits: &mut Vec<TreeEdit>,
+) -> DoogieResult<()> {
+    if attributes(a)? != attributes(b)? {
+        edits.push(TreeEdit::Change {
+            old_path: a_path.clone(),
+            new_path: b_path.clone(),
+        });
+    }
+
+    let a_children = children(a)?;
+    let b_children = children(b)?;
+    let matches = longest_common_subsequence(&a_children, &b_children);
+
+    let (mut i, mut j) = (0, 0);
+    for &(next_i, next_j) in matches
+        .iter()
+        .chain(Some((a_children.len(), b_children.len())).iter())
+    {
+        while i < next_i
+            && j < next_j
+            && a_children[i].0.get_cmark_type()? == b_children[j].0.get_cmark_type()?
+        {
+            a_path.push(i);
+            b_path.push(j);
+            diff_nodes(&a_children[i].0, &b_children[j].0, a_path, b_path, edits)?;
+            a_path.pop();
+            b_path.pop();
+            i += 1;
+            j += 1;
+        }
+        for removed in i..next_i {
+            edits.push(TreeEdit::Remove {
+                path: child_path(a_path, removed),
+            });
+        }
+        for inserted in j..next_j {
+            edits.push(TreeEdit::Insert {
+                path: child_path(b_path, inserted),
+            });
+        }
+
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+
+    Ok(())
+}
+
+/// Returns the children of a `Node` along with the XML of their subtrees
+fn children(node: &Node) -> DoogieResult<Vec<(Node, String)>> {
+    let mut children = Vec::new();
+    let mut next = node.first_child()?;
+    while let Some(child) = next {
+        next = child.next_sibling()?;
+        let xml = child.render_xml();
+        children.push((child, xml));
+    }
+
+    Ok(children)
+}
+
+/// Returns the XML of a `Node` without its children
+fn attributes(node: &Node) -> DoogieResult<String> {
+    let mut copy = Node::from_type(node.get_cmark_type()?)?;
+    copy.copy_attributes(node)?;
+    copy.manager().track_root(&copy.pointer());
+
+    Ok(copy.render_xml())
+}
+
+/// Returns the index pairs of the longest common subsequence of identical subtrees
+fn longest_common_subsequence(a: &[(Node, String)], b: &[(Node, String)]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i].1 == b[j].1 {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                ::std::cmp::max(lengths[i + 1][j], lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].1 == b[j].1 {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches
+}
+
+/// Returns the path of the child at `index` of the `Node` at `path`
+fn child_path(path: &[usize], index: usize) -> Vec<usize> {
+    let mut child = path.to_vec();
+    child.push(index);
+    child
+}
@@ -1,3 +1,4 @@
+use constants::NodeType;
 use std::error;
 use std::ffi::NulError;
 use std::fmt;
@@ -9,12 +10,18 @@ use std::str::Utf8Error;
 pub enum DoogieError {
     NulError(NulError),
     Utf8Error(Utf8Error),
-    ReturnCode(u32),
+    ReturnCode { code: u32, op: &'static str },
     BadEnum(u32),
     IOError(IOError),
     ResourceUnavailable,
     NodeNone,
     FmtError(fmt::Error),
+    InvalidChild { parent: NodeType, child: NodeType },
+    NoParent,
+    InvalidArgument(String),
+    WrongNodeType { expected: NodeType, found: NodeType },
+    XmlError(String),
+    SourceUnavailable,
 }
 
 impl fmt::Display for DoogieError {
@@ -23,13 +30,25 @@ impl fmt::Display for DoogieError {
             DoogieError::NulError(ref err) => write!(f, "NulError: {}", err),
             DoogieError::Utf8Error(ref err) => write!(f, "Utf8Error: {}", err),
             DoogieError::IOError(ref err) => write!(f, "IOError: {}", err),
-            DoogieError::ReturnCode(code) => write!(f, "CMark return code: {}", code),
+            DoogieError::ReturnCode { code, op } => write!(f, "{} failed with code {}", op, code),
             DoogieError::BadEnum(num) => write!(f, "Bad Enum Value: {}", num),
             DoogieError::ResourceUnavailable => write!(f, "The resource is no longer available"),
             DoogieError::NodeNone => {
                 write!(f, "CMark has erroneously returned null for this operation")
             }
             DoogieError::FmtError(ref err) => write!(f, "FmtError: {}", err),
+            DoogieError::InvalidChild {
+                ref parent,
+                ref child,
+            } => write!(f, "{:?} cannot contain {:?}", parent, child),
+            DoogieError::NoParent => write!(f, "The node has no parent"),
+            DoogieError::InvalidArgument(ref reason) => write!(f, "Invalid argument: {}", reason),
+            DoogieError::WrongNodeType {
+                ref expected,
+                ref found,
+            } => write!(f, "Expected {:?} but found {:?}", expected, found),
+            DoogieError::XmlError(ref reason) => write!(f, "XmlError: {}", reason),
+            DoogieError::SourceUnavailable => write!(f, "The source of the node is unavailable"),
         }
     }
 }
@@ -40,11 +59,17 @@ impl error::Error for DoogieError {
             DoogieError::NulError(ref err) => err.description(),
             DoogieError::Utf8Error(ref err) => err.description(),
             DoogieError::IOError(ref err) => err.description(),
-            DoogieError::ReturnCode(_code) => "libcmark returned an error code.",
+            DoogieError::ReturnCode { .. } => "libcmark returned an error code.",
             DoogieError::BadEnum(_num) => "libcmark returned an invalid node type.",
             DoogieError::ResourceUnavailable => "The resource is no longer available.",
             DoogieError::NodeNone => "libcmark returned Node::None which is an error.",
             DoogieError::FmtError(ref err) => err.description(),
+            DoogieError::InvalidChild { .. } => "The child type is not allowed in the parent.",
+            DoogieError::NoParent => "The node has no parent.",
+            DoogieError::InvalidArgument(_) => "An argument was invalid.",
+            DoogieError::WrongNodeType { .. } => "The node is not of the expected type.",
+            DoogieError::XmlError(_) => "The XML could not be parsed.",
+            DoogieError::SourceUnavailable => "The source of the node is unavailable.",
         }
     }
 
@@ -53,11 +78,17 @@ impl error::Error for DoogieError {
             DoogieError::NulError(ref err) => Some(err),
             DoogieError::Utf8Error(ref err) => Some(err),
             DoogieError::IOError(ref err) => Some(err),
-            DoogieError::ReturnCode(_code) => None,
+            DoogieError::ReturnCode { .. } => None,
             DoogieError::BadEnum(_num) => None,
             DoogieError::ResourceUnavailable => None,
             DoogieError::NodeNone => None,
             DoogieError::FmtError(ref err) => Some(err),
+            DoogieError::InvalidChild { .. } => None,
+            DoogieError::NoParent => None,
+            DoogieError::InvalidArgument(_) => None,
+            DoogieError::WrongNodeType { .. } => None,
+            DoogieError::XmlError(_) => None,
+            DoogieError::SourceUnavailable => None,
         }
     }
 }
@@ -1,5 +1,3 @@
-#![feature(vec_remove_item)]
-
 #[cfg(test)]
 #[macro_use]
 extern crate proptest;
@@ -13,17 +11,24 @@ extern crate libc;
 extern crate try_from;
 
 pub mod constants;
+mod diff;
 pub mod errors;
+mod xml;
 
 use self::libc::{c_char, c_int, c_void, size_t};
 use self::try_from::TryFrom;
 use constants::*;
 use errors::DoogieError;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::fmt::{Debug, Error, Formatter};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+pub use diff::{diff, TreeEdit};
+pub use xml::parse_xml;
 
 /// Result type for the Doogie crate
 pub type DoogieResult<T> = Result<T, DoogieError>;
@@ -32,6 +37,8 @@ pub type DoogieResult<T> = Result<T, DoogieError>;
 pub enum CMarkNodePtr {}
 /// Represents libcmark iterator pointers as an opaque struct
 enum CMarkIterPtr {}
+/// Represents libcmark parser pointers as an opaque struct
+enum CMarkParserPtr {}
 
 extern "C" {
     fn cmark_node_new(node_type: u32) -> *mut CMarkNodePtr;
@@ -40,28 +47,70 @@ extern "C" {
 
     fn cmark_node_free(node: *mut CMarkNodePtr);
 
+    fn cmark_parser_new(options: c_int) -> *mut CMarkParserPtr;
+
+    fn cmark_parser_feed(parser: *mut CMarkParserPtr, buffer: *const u8, len: size_t) -> c_void;
+
+    fn cmark_parser_finish(parser: *mut CMarkParserPtr) -> *mut CMarkNodePtr;
+
+    fn cmark_parser_free(parser: *mut CMarkParserPtr) -> c_void;
+
     fn cmark_node_get_literal(node: *mut CMarkNodePtr) -> *const c_char;
 
     fn cmark_node_set_literal(node: *mut CMarkNodePtr, content: *const c_char) -> c_int;
 
     fn cmark_node_get_type(node: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_get_user_data(node: *mut CMarkNodePtr) -> *mut c_void;
+
+    fn cmark_node_set_user_data(node: *mut CMarkNodePtr, user_data: *mut c_void) -> c_int;
+
     fn cmark_node_get_type_string(node: *mut CMarkNodePtr) -> *const c_char;
 
     fn cmark_node_get_start_line(node: *mut CMarkNodePtr) -> c_int;
 
     fn cmark_node_get_start_column(node: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_get_end_line(node: *mut CMarkNodePtr) -> c_int;
+
+    fn cmark_node_get_end_column(node: *mut CMarkNodePtr) -> c_int;
+
     fn cmark_node_get_list_type(node: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_set_list_type(node: *mut CMarkNodePtr, list_type: c_int) -> c_int;
+
     fn cmark_node_get_list_delim(node: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_set_list_delim(node: *mut CMarkNodePtr, delim: c_int) -> c_int;
+
+    fn cmark_node_get_list_start(node: *mut CMarkNodePtr) -> c_int;
+
+    fn cmark_node_set_list_start(node: *mut CMarkNodePtr, start: c_int) -> c_int;
+
+    fn cmark_node_get_list_tight(node: *mut CMarkNodePtr) -> c_int;
+
+    fn cmark_node_set_list_tight(node: *mut CMarkNodePtr, tight: c_int) -> c_int;
+
     fn cmark_node_get_heading_level(node: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_set_heading_level(node: *mut CMarkNodePtr, level: c_int) -> c_int;
+
     fn cmark_node_get_url(node: *mut CMarkNodePtr) -> *const c_char;
 
+    fn cmark_node_set_url(node: *mut CMarkNodePtr, url: *const c_char) -> c_int;
+
     fn cmark_node_get_title(node: *mut CMarkNodePtr) -> *const c_char;
 
+    fn cmark_node_set_title(node: *mut CMarkNodePtr, title: *const c_char) -> c_int;
+
+    fn cmark_node_get_on_enter(node: *mut CMarkNodePtr) -> *const c_char;
+
+    fn cmark_node_set_on_enter(node: *mut CMarkNodePtr, on_enter: *const c_char) -> c_int;
+
+    fn cmark_node_get_on_exit(node: *mut CMarkNodePtr) -> *const c_char;
+
+    fn cmark_node_set_on_exit(node: *mut CMarkNodePtr, on_exit: *const c_char) -> c_int;
+
     fn cmark_node_get_fence_info(node: *mut CMarkNodePtr) -> *const c_char;
 
     fn cmark_node_set_fence_info(node: *mut CMarkNodePtr, info: *const c_char) -> c_int;
@@ -80,12 +129,20 @@ extern "C" {
 
     fn cmark_node_append_child(node: *mut CMarkNodePtr, child: *mut CMarkNodePtr) -> c_int;
 
+    fn cmark_node_insert_before(node: *mut CMarkNodePtr, sibling: *mut CMarkNodePtr) -> c_int;
+
+    fn cmark_node_insert_after(node: *mut CMarkNodePtr, sibling: *mut CMarkNodePtr) -> c_int;
+
+    fn cmark_node_prepend_child(node: *mut CMarkNodePtr, child: *mut CMarkNodePtr) -> c_int;
+
     fn cmark_consolidate_text_nodes(root: *mut CMarkNodePtr) -> c_void;
 
     fn cmark_render_xml(root: *mut CMarkNodePtr, options: c_int) -> *const c_char;
 
     fn cmark_render_commonmark(root: *mut CMarkNodePtr, options: c_int) -> *const c_char;
 
+    fn cmark_render_html(root: *mut CMarkNodePtr, options: c_int) -> *const c_char;
+
     fn cmark_iter_new(node: *mut CMarkNodePtr) -> *mut CMarkIterPtr;
 
     fn cmark_iter_get_node(iter: *mut CMarkIterPtr) -> *mut CMarkNodePtr;
@@ -115,6 +172,15 @@ impl Resource {
         }
         Self { pointer, manager }
     }
+
+    /// Returns the libcmark pointer if its memory has not been freed by `Node::close`
+    fn checked_pointer(&self) -> DoogieResult<*mut CMarkNodePtr> {
+        if self.manager.is_freed() {
+            Err(DoogieError::ResourceUnavailable)
+        } else {
+            Ok(self.pointer)
+        }
+    }
 }
 
 /// Parses the text of a CommonMark document and returns the root node of the document tree.
@@ -132,16 +198,37 @@ impl Resource {
 ///
 /// let root = parse_document(document);
 /// ```
+///
+/// The source text is retained alongside the document so that the original Markdown of any of its
+/// `Node`s can be retrieved with `Node::source_text`.
 pub fn parse_document(buffer: &str) -> Node {
+    parse_document_with_options(buffer, DoogieOptions::default())
+}
+
+/// Parses a CommonMark document with the given libcmark options
+///
+/// This behaves like `parse_document` apart from the options, of which `SMART`, `NORMALIZE` and
+/// `VALIDATE_UTF8` affect parsing.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::constants::DoogieOptions;
+/// use doogie::parse_document_with_options;
+///
+/// let root = parse_document_with_options("\"Quoted\" -- text", DoogieOptions::SMART);
+/// assert_eq!(root.inner_text().unwrap(), "\u{201c}Quoted\u{201d} \u{2013} text");
+/// ```
+pub fn parse_document_with_options(buffer: &str, options: DoogieOptions) -> Node {
+    let manager = Rc::new(ResourceManager::with_source(buffer.to_string()));
     let buffer = buffer.as_bytes();
     let buffer_len = buffer.len() as size_t;
     let p_buffer = buffer.as_ptr();
-    let manager = Rc::new(ResourceManager::new());
     let root_ptr: *mut CMarkNodePtr;
     unsafe {
-        root_ptr = cmark_parse_document(p_buffer, buffer_len, 0);
+        root_ptr = cmark_parse_document(p_buffer, buffer_len, options.bits() as c_int);
     }
-    manager.track_root(&root_ptr);
+    manager.track_parsed_root(&root_ptr);
 
     Node::Document(Document {
         resource: Resource {
@@ -151,6 +238,276 @@ pub fn parse_document(buffer: &str) -> Node {
     })
 }
 
+/// A libcmark parser that can be reused for many documents
+///
+/// The text of a document may be fed to the parser in several pieces. Finishing the document
+/// resets the parser, which then parses the next document while reusing its allocations. This
+/// amortizes the setup cost of the parser when parsing many small documents.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::Parser;
+///
+/// let mut parser = Parser::new();
+/// for message in &["Hello *world*", "# Title"] {
+///     parser.feed(message);
+///     let root = parser.finish_and_reset();
+///     println!("{}", root.render_xml());
+/// }
+/// ```
+pub struct Parser {
+    /// Raw libcmark parser pointer.
+    pointer: *mut CMarkParserPtr,
+    /// Text fed to the parser since the last document was finished.
+    source: String,
+}
+
+impl Parser {
+    /// Constructs a new `Parser` with the default options used by `parse_document`
+    pub fn new() -> Self {
+        Parser::with_options(DoogieOptions::default())
+    }
+
+    /// Constructs a new `Parser` with the given libcmark options
+    pub fn with_options(options: DoogieOptions) -> Self {
+        let pointer;
+        unsafe {
+            pointer = cmark_parser_new(options.bits() as c_int);
+        }
+
+        Parser {
+            pointer,
+            source: String::new(),
+        }
+    }
+
+    /// Feeds the next piece of the text of the current document to the parser
+    pub fn feed(&mut self, buffer: &str) {
+        unsafe {
+            cmark_parser_feed(self.pointer, buffer.as_ptr(), buffer.len() as size_t);
+        }
+        self.source.push_str(buffer);
+    }
+
+    /// Finishes parsing the current document, returning its root, and resets the parser
+    ///
+    /// Like `parse_document`, the text of the document is retained alongside it.
+    pub fn finish_and_reset(&mut self) -> Node {
+        let source = std::mem::replace(&mut self.source, String::new());
+        let manager = Rc::new(ResourceManager::with_source(source));
+        let root_ptr: *mut CMarkNodePtr;
+        unsafe {
+            root_ptr = cmark_parser_finish(self.pointer);
+        }
+        manager.track_parsed_root(&root_ptr);
+
+        Node::Document(Document {
+            resource: Resource {
+                pointer: root_ptr,
+                manager,
+            },
+        })
+    }
+}
+
+impl Drop for Parser {
+    fn drop(&mut self) {
+        unsafe {
+            cmark_parser_free(self.pointer);
+        }
+    }
+}
+
+/// Parses a CommonMark document that may begin with a YAML front matter block.
+///
+/// Front matter is only recognized when the very first line of the buffer is `---` and a later
+/// line consisting of `---` or `...` closes the block. The text between the delimiter lines is
+/// returned along with the root node of the remainder of the document, which is parsed as with
+/// `parse_document`. Line positions of the returned `Node`s are therefore relative to the body.
+/// If there is no such block, `None` is returned along with the whole document.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::parse_document_with_frontmatter;
+///
+/// let document = "---\ntitle: My Great Document\n---\n# Heading\n";
+///
+/// let (frontmatter, root) = parse_document_with_frontmatter(document).unwrap();
+/// assert_eq!(frontmatter, Some("title: My Great Document\n".to_string()));
+/// ```
+pub fn parse_document_with_frontmatter(buffer: &str) -> DoogieResult<(Option<String>, Node)> {
+    let mut lines = buffer.split('\n');
+    let opens = lines
+        .next()
+        .map_or(false, |line| line.trim_right() == "---");
+
+    if opens {
+        let mut offset = buffer
+            .find('\n')
+            .map_or(buffer.len(), |newline| newline + 1);
+        let start = offset;
+        for line in lines {
+            let end = offset;
+            offset = std::cmp::min(offset + line.len() + 1, buffer.len());
+
+            let delimiter = line.trim_right();
+            if delimiter == "---" || delimiter == "..." {
+                let frontmatter = buffer[start..end].to_string();
+                return Ok((Some(frontmatter), parse_document(&buffer[offset..])));
+            }
+        }
+    }
+
+    Ok((None, parse_document(buffer)))
+}
+
+/// Backslash-escapes the characters of `text` that CommonMark would interpret as markup
+///
+/// Characters that introduce inline markup are escaped everywhere, while characters that only
+/// have a meaning at the start of a line, such as heading, block quote and list markers, are
+/// escaped only there. Spaces and tabs that would be stripped from the start or end of a line,
+/// carriage returns and the line endings next to blank lines are written as character references
+/// instead, so the escaped text parses as a single paragraph whose text, with soft breaks read as
+/// newlines, is the original text. The only exception is NUL, which CommonMark replaces with
+/// U+FFFD.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::escape_commonmark;
+///
+/// assert_eq!(escape_commonmark("*not bold*"), "\\*not bold\\*");
+/// ```
+pub fn escape_commonmark(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut escaped = String::with_capacity(text.len());
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            // A blank line would end the paragraph, so the line endings around it are kept as text
+            if line.is_empty() || lines[index - 1].is_empty() {
+                escaped.push_str("&#10;");
+            } else {
+                escaped.push('\n');
+            }
+        }
+
+        let indent = line.len() - line.trim_left().len();
+        let digits = line[indent..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(line.len() - indent);
+        for (offset, c) in line.char_indices() {
+            let stripped = (c == ' ' || c == '\t') && (offset == 0 || offset + 1 == line.len());
+            if stripped || c == '\r' {
+                escaped.push_str(&format!("&#{};", c as u32));
+                continue;
+            }
+
+            let line_start = offset == indent
+                || (offset == indent + digits && digits > 0 && (c == '.' || c == ')'));
+            let special = match c {
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '&' | '!' => true,
+                '#' | '+' | '-' | '=' | '~' => line_start,
+                '.' | ')' => line_start && offset != indent,
+                _ => false,
+            };
+
+            if special {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+
+    escaped
+}
+
+/// Replaces the HTML character references in `text` with the characters they represent
+///
+/// Numeric references and the named references of `constants::HTML_ENTITIES` are decoded.
+/// Unknown or malformed references, including those missing their closing `;`, are left as they
+/// are.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::decode_entities;
+///
+/// assert_eq!(decode_entities("&copy; 2018 &amp; &#x263A; &unknown;"), "© 2018 & ☺ &unknown;");
+/// ```
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(ampersand) = rest.find('&') {
+        decoded.push_str(&rest[..ampersand]);
+        rest = &rest[ampersand..];
+
+        let reference = rest
+            .find(';')
+            .and_then(|semicolon| decode_entity(&rest[1..semicolon]).map(|c| (c, semicolon)));
+        match reference {
+            Some((character, semicolon)) => {
+                decoded.push_str(&character);
+                rest = &rest[semicolon + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// Summary statistics of a document subtree, as returned by `Node::statistics`
+#[derive(Debug, Default, PartialEq)]
+pub struct DocStats {
+    /// Number of words, as counted by `Node::word_count`
+    pub words: usize,
+    /// Number of characters in the `Node::inner_text`
+    pub characters: usize,
+    /// Number of `Heading` Nodes
+    pub headings: usize,
+    /// Number of `Link` Nodes
+    pub links: usize,
+    /// Number of `Image` Nodes
+    pub images: usize,
+    /// Number of `CodeBlock` Nodes
+    pub code_blocks: usize,
+}
+
+/// A heading in the outline of a document, as returned by `Node::outline`
+#[derive(Debug, PartialEq)]
+pub struct OutlineNode {
+    /// Level of the heading, from 1 to 6
+    pub level: usize,
+    /// Inner text of the heading
+    pub title: String,
+    /// Headings nested under this heading
+    pub children: Vec<OutlineNode>,
+}
+
+/// A structural change to a document tree, as reported to `Node::observe_mutations`
+///
+/// `Node`s are identified by the full address of their libcmark node, as returned by
+/// `Node::as_ptr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MutationEvent {
+    /// `child` was appended as the last child of `parent`
+    AppendChild { parent: usize, child: usize },
+    /// `child` was inserted as the first child of `parent`
+    PrependChild { parent: usize, child: usize },
+    /// `node` was inserted as the sibling immediately preceding `before`
+    InsertBefore { node: usize, before: usize },
+    /// `node` was inserted as the sibling immediately following `after`
+    InsertAfter { node: usize, after: usize },
+    /// `node` was unlinked from `parent`
+    Unlink { node: usize, parent: usize },
+}
+
 /// Exposes the internal pointer and memory management of a `Node`
 trait NodeResource {
     /// Returns the libcmark node pointer
@@ -256,11 +613,11 @@ impl Debug for Node {
 
 impl Node {
     /// Construct a Rust Node wrapper around a pointer to a libcmark node
-    fn from_raw(pointer: *mut CMarkNodePtr) -> DoogieResult<Self> {
-        let resource = Resource {
-            pointer,
-            manager: Rc::new(ResourceManager::new()),
-        };
+    ///
+    /// The wrapper shares the given `ResourceManager`, which should be the manager of the tree
+    /// that the pointer belongs to.
+    fn from_raw(pointer: *mut CMarkNodePtr, manager: Rc<ResourceManager>) -> DoogieResult<Self> {
+        let resource = Resource { pointer, manager };
 
         let cmark_type: NodeType;
         unsafe {
@@ -293,34 +650,242 @@ impl Node {
         Ok(result)
     }
 
+    /// Wraps a libcmark node pointer whose memory is owned by other code
+    ///
+    /// The returned `Node`, and every `Node` reached from it, will never free the memory of the
+    /// tree, including any subtrees that are unlinked from it. A `DoogieError::NodeNone` error is
+    /// returned if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to a valid libcmark node, and the tree it belongs to must remain
+    /// valid, and must not be freed by its owner, for as long as any `Node` wrapping part of it is
+    /// alive. The owner remains responsible for freeing the tree, including any `Node`s appended
+    /// to it through the wrapper.
+    pub unsafe fn from_borrowed_raw(pointer: *mut CMarkNodePtr) -> DoogieResult<Node> {
+        if pointer.is_null() {
+            return Err(DoogieError::NodeNone);
+        }
+
+        Node::from_raw(pointer, Rc::new(ResourceManager::borrowed()))
+    }
+
+    /// Returns the libcmark node pointer of the current `Node` without transferring ownership
+    ///
+    /// This allows calling libcmark functions that are not wrapped by this crate. The pointer is
+    /// only valid while the tree that the `Node` belongs to is alive, and must not be freed.
+    pub fn as_ptr(&self) -> *mut CMarkNodePtr {
+        self.pointer()
+    }
+
+    /// Consumes the `Node` and returns its libcmark node pointer, transferring ownership of the
+    /// memory to the caller
+    ///
+    /// The `Node` is unlinked from its parent, if it has one, and is no longer tracked by its
+    /// `ResourceManager`, so the returned pointer is the root of a tree that will not be freed by
+    /// this crate. The caller is responsible for eventually freeing it, e.g. with
+    /// `cmark_node_free`. Other `Node`s wrapping parts of the tree must not be used afterwards.
+    pub fn into_raw(mut self) -> *mut CMarkNodePtr {
+        self.unlink();
+        let pointer = self.pointer();
+        self.manager().untrack_root(&pointer);
+
+        pointer
+    }
+
     /// Constructs a new `Node` of the given libcmark Node Type
     pub fn from_type(node_type: NodeType) -> DoogieResult<Self> {
         let pointer: *mut CMarkNodePtr;
         unsafe {
             pointer = cmark_node_new(node_type as u32);
         }
-        Node::from_raw(pointer)
+        Node::from_raw(pointer, Rc::new(ResourceManager::new()))
+    }
+
+    /// Parses a CommonMark fragment and returns its top level blocks as detached `Node`s
+    ///
+    /// Each block is unlinked with `detach`, so the blocks outlive the temporary document they were
+    /// parsed into and are ready to be appended elsewhere.
+    pub fn parse_fragment(md: &str) -> DoogieResult<Vec<Node>> {
+        let root = parse_document(md);
+        let mut blocks = Vec::new();
+        for mut block in root.children()? {
+            blocks.push(block.detach()?);
+        }
+
+        Ok(blocks)
     }
 
     /// Returns the Rust equivalent of a libcmark NodeType enum
     pub fn get_cmark_type(&self) -> DoogieResult<NodeType> {
         let t: i32;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            t = cmark_node_get_type(self.pointer());
+            t = cmark_node_get_type(pointer);
         }
         Ok(NodeType::try_from(t as u32)?)
     }
 
+    /// Returns the current `Node` as a `Document` if it is one
+    pub fn as_document(&self) -> Option<&Document> {
+        match *self {
+            Node::Document(ref document) => Some(document),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `List` if it is one
+    pub fn as_list(&self) -> Option<&List> {
+        match *self {
+            Node::List(ref list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `CodeBlock` if it is one
+    pub fn as_code_block(&self) -> Option<&CodeBlock> {
+        match *self {
+            Node::CodeBlock(ref code_block) => Some(code_block),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `Heading` if it is one
+    pub fn as_heading(&self) -> Option<&Heading> {
+        match *self {
+            Node::Heading(ref heading) => Some(heading),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `Text` if it is one
+    pub fn as_text(&self) -> Option<&Text> {
+        match *self {
+            Node::Text(ref text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `Code` if it is one
+    pub fn as_code(&self) -> Option<&Code> {
+        match *self {
+            Node::Code(ref code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as an `HtmlInline` if it is one
+    pub fn as_html_inline(&self) -> Option<&HtmlInline> {
+        match *self {
+            Node::HtmlInline(ref html_inline) => Some(html_inline),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a `Link` if it is one
+    pub fn as_link(&self) -> Option<&Link> {
+        match *self {
+            Node::Link(ref link) => Some(link),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as an `Image` if it is one
+    pub fn as_image(&self) -> Option<&Image> {
+        match *self {
+            Node::Image(ref image) => Some(image),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `Document` if it is one
+    pub fn as_document_mut(&mut self) -> Option<&mut Document> {
+        match *self {
+            Node::Document(ref mut document) => Some(document),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `List` if it is one
+    pub fn as_list_mut(&mut self) -> Option<&mut List> {
+        match *self {
+            Node::List(ref mut list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `CodeBlock` if it is one
+    pub fn as_code_block_mut(&mut self) -> Option<&mut CodeBlock> {
+        match *self {
+            Node::CodeBlock(ref mut code_block) => Some(code_block),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `Heading` if it is one
+    pub fn as_heading_mut(&mut self) -> Option<&mut Heading> {
+        match *self {
+            Node::Heading(ref mut heading) => Some(heading),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `Text` if it is one
+    pub fn as_text_mut(&mut self) -> Option<&mut Text> {
+        match *self {
+            Node::Text(ref mut text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `Code` if it is one
+    pub fn as_code_mut(&mut self) -> Option<&mut Code> {
+        match *self {
+            Node::Code(ref mut code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as an mutable `HtmlInline` if it is one
+    pub fn as_html_inline_mut(&mut self) -> Option<&mut HtmlInline> {
+        match *self {
+            Node::HtmlInline(ref mut html_inline) => Some(html_inline),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as a mutable `Link` if it is one
+    pub fn as_link_mut(&mut self) -> Option<&mut Link> {
+        match *self {
+            Node::Link(ref mut link) => Some(link),
+            _ => None,
+        }
+    }
+
+    /// Returns the current `Node` as an mutable `Image` if it is one
+    pub fn as_image_mut(&mut self) -> Option<&mut Image> {
+        match *self {
+            Node::Image(ref mut image) => Some(image),
+            _ => None,
+        }
+    }
+
     /// Returns a unique numerical identity for the `Node`
     pub fn get_id(&self) -> u32 {
         self.pointer() as u32
     }
 
+    /// Returns the identity of the `Node` in `MutationEvent`s
+    fn event_id(&self) -> usize {
+        self.pointer() as usize
+    }
+
     /// Returns a string version of the Node type
     pub fn get_cmark_type_string(&self) -> DoogieResult<String> {
         let result;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            result = cmark_node_get_type_string(self.pointer());
+            result = cmark_node_get_type_string(pointer);
         }
 
         if result.is_null() {
@@ -331,1030 +896,6597 @@ impl Node {
         }
     }
 
+    /// Returns the libcmark node pointer if its memory has not been freed by `close`
+    fn checked_pointer(&self) -> DoogieResult<*mut CMarkNodePtr> {
+        if self.manager().is_freed() {
+            Err(DoogieError::ResourceUnavailable)
+        } else {
+            Ok(self.pointer())
+        }
+    }
+
+    /// Returns the number of root `Node`s tracked by the `ResourceManager` of the current `Node`
+    ///
+    /// A parsed document tracks its root, and each `Node` unlinked from it is tracked as the root
+    /// of a separate subtree until it is appended elsewhere. This is intended for verifying memory
+    /// bookkeeping in tests. It reflects internal state, so the exact counts may change between
+    /// releases.
+    pub fn tracked_root_count(&self) -> usize {
+        self.manager().root_count()
+    }
+
+    /// Frees the memory of the document that the current `Node` belongs to immediately
+    ///
+    /// Normally the memory is released once every `Node` of the document has been dropped. This
+    /// releases it deterministically instead: every tree managed along with the current `Node`,
+    /// which for a parsed document is the whole document, is freed. Moving a `Node` between
+    /// documents makes them managed together, so this also frees the documents that `Node`s were
+    /// moved in from or out to. Other `Node`s of these documents, including the moved ones,
+    /// never access the freed memory: their fallible methods return
+    /// `DoogieError::ResourceUnavailable`, iterators end, and methods that cannot fail, such as
+    /// the renderers and the source position getters, return empty strings, zero or `false`.
+    /// Closing a `Node` whose memory was already freed returns the same error.
+    pub fn close(self) -> DoogieResult<()> {
+        self.checked_pointer()?;
+        self.manager().free();
+
+        Ok(())
+    }
+
     /// Returns the next sequential sibling of the current `Node` if it exists
     pub fn next_sibling(&self) -> DoogieResult<Option<Node>> {
         let next_node_ptr: *mut CMarkNodePtr;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            next_node_ptr = cmark_node_next(self.pointer());
+            next_node_ptr = cmark_node_next(pointer);
         }
 
         if next_node_ptr.is_null() {
             Ok(None)
         } else {
-            Ok(Some(Node::from_raw(next_node_ptr)?))
+            Ok(Some(Node::from_raw(next_node_ptr, self.manager())?))
         }
     }
 
     /// Returns the previous sequential sibling of the current `Node` if it exists
     pub fn prev_sibling(&self) -> DoogieResult<Option<Node>> {
         let prev_node_ptr: *mut CMarkNodePtr;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            prev_node_ptr = cmark_node_previous(self.pointer());
+            prev_node_ptr = cmark_node_previous(pointer);
         }
 
         if prev_node_ptr.is_null() {
             Ok(None)
         } else {
-            Ok(Some(Node::from_raw(prev_node_ptr)?))
+            Ok(Some(Node::from_raw(prev_node_ptr, self.manager())?))
+        }
+    }
+
+    /// Returns an iterator over all of the siblings that follow the current `Node`
+    ///
+    /// The siblings are yielded in document order, starting with the next sibling.
+    pub fn following_siblings(&self) -> impl Iterator<Item = Node> {
+        SiblingIterator {
+            pointer: self.pointer(),
+            manager: self.manager(),
+            forward: true,
+        }
+    }
+
+    /// Returns an iterator over all of the siblings that precede the current `Node`
+    ///
+    /// The siblings are yielded in reverse document order, starting with the previous sibling and
+    /// ending with the first child of the parent.
+    pub fn preceding_siblings(&self) -> impl Iterator<Item = Node> {
+        SiblingIterator {
+            pointer: self.pointer(),
+            manager: self.manager(),
+            forward: false,
         }
     }
 
     /// Returns the parent Node of the current `Node` if it exists
     pub fn parent(&self) -> DoogieResult<Option<Node>> {
         let parent_node_ptr: *mut CMarkNodePtr;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            parent_node_ptr = cmark_node_parent(self.pointer());
+            parent_node_ptr = cmark_node_parent(pointer);
         }
 
         if parent_node_ptr.is_null() {
             Ok(None)
         } else {
-            Ok(Some(Node::from_raw(parent_node_ptr)?))
+            Ok(Some(Node::from_raw(parent_node_ptr, self.manager())?))
         }
     }
 
-    /// Returns the first child Node of the current `Node` if it exists
-    pub fn first_child(&self) -> DoogieResult<Option<Node>> {
-        let child_ptr: *mut CMarkNodePtr;
-        unsafe {
-            child_ptr = cmark_node_first_child(self.pointer());
+    /// Returns the root of the tree that the current `Node` belongs to
+    ///
+    /// This is the `Document` for a `Node` of a parsed document, or the `Node` itself if it has no
+    /// parent.
+    pub fn root(&self) -> DoogieResult<Node> {
+        let mut root = self.itself()?;
+        while let Some(parent) = root.parent()? {
+            root = parent;
         }
 
-        if child_ptr.is_null() {
-            Ok(None)
-        } else {
-            Ok(Some(Node::from_raw(child_ptr)?))
-        }
+        Ok(root)
     }
 
-    /// Returns the last child Node of the current `Node` if it exists
-    pub fn last_child(&self) -> DoogieResult<Option<Node>> {
-        let child_ptr: *mut CMarkNodePtr;
-        unsafe {
-            child_ptr = cmark_node_last_child(self.pointer());
-        }
-
-        if child_ptr.is_null() {
-            Ok(None)
-        } else {
-            Ok(Some(Node::from_raw(child_ptr)?))
+    /// Returns whether the current `Node` is a strict descendant of the given `Node`
+    ///
+    /// The ancestors of the current `Node` are compared with the given `Node` by identity, so a
+    /// `Node` is not a descendant of itself.
+    pub fn is_descendant_of(&self, ancestor: &Node) -> DoogieResult<bool> {
+        let mut next = self.parent()?;
+        while let Some(parent) = next {
+            if parent.pointer() == ancestor.pointer() {
+                return Ok(true);
+            }
+            next = parent.parent()?;
         }
-    }
 
-    /// Returns a new instance of the current `Node`
-    ///
-    /// The returned `Node` will share the underlying memory resource and manager of the current Node.
-    pub fn itself(&self) -> DoogieResult<Node> {
-        Ok(Node::from_raw(self.pointer())?)
+        Ok(false)
     }
 
-    /// Unlinks the current `Node` from its position in the document AST
+    /// Returns whether the current `Node` and the given `Node` have the same parent
     ///
-    /// After unlinking, the Node will have no parent or siblings, but will retain all of its
-    /// children.
-    pub fn unlink(&mut self) {
-        unsafe {
-            cmark_node_unlink(self.pointer());
+    /// The parents are compared by identity. `Node`s without a parent are never siblings, while a
+    /// `Node` that has a parent is a sibling of itself.
+    pub fn is_sibling_of(&self, other: &Node) -> DoogieResult<bool> {
+        match (self.parent()?, other.parent()?) {
+            (Some(parent), Some(other_parent)) => Ok(parent.pointer() == other_parent.pointer()),
+            _ => Ok(false),
         }
-        self.manager().track_root(&self.pointer());
     }
 
-    /// Append the given `Node` as the last child of the current `Node` if possible
+    /// Returns the children of the current `Node` from `start` through `end` inclusive, in
+    /// document order
     ///
-    /// The rules of the CommonMark AST must be respected when appending nodes. Not all Nodes can
-    /// be appended to each particular type of Node. Use `can_append_child` to determine if the
-    /// operation will succeed. An error will be returned along with the libcmark error code if the
-    /// operation.
-    pub fn append_child(&mut self, child: &mut Node) -> DoogieResult<()> {
-        child.unlink();
-        let result: i32;
-        unsafe {
-            result = cmark_node_append_child(self.pointer(), child.pointer());
+    /// A `DoogieError::InvalidArgument` error is returned if `start` and `end` are not both
+    /// children of the current `Node` or if `end` precedes `start`.
+    pub fn range_between(&self, start: &Node, end: &Node) -> DoogieResult<Vec<Node>> {
+        let parent = start.parent()?.map(|parent| parent.pointer());
+        if parent != Some(self.pointer()) || !start.is_sibling_of(end)? {
+            return Err(DoogieError::InvalidArgument(
+                "start and end must be children of the node".to_string(),
+            ));
         }
 
-        match result {
-            1 => {
-                child.manager().untrack_root(&child.pointer());
-                Ok(())
+        let mut range = vec![start.itself()?];
+        if start.pointer() == end.pointer() {
+            return Ok(range);
+        }
+        for sibling in start.following_siblings() {
+            let is_end = sibling.pointer() == end.pointer();
+            range.push(sibling);
+            if is_end {
+                return Ok(range);
             }
-            i => Err(DoogieError::ReturnCode(i as u32)),
         }
+
+        Err(DoogieError::InvalidArgument(
+            "end must not precede start".to_string(),
+        ))
     }
 
-    /// Determines if the given `Node` is a potentially valid child of the current `Node`
-    pub fn can_append_child(&self, child: &Node) -> DoogieResult<bool> {
-        let child_type = child.get_cmark_type()?;
+    /// Unlinks the children of the current `Node` from `start` through `end` inclusive
+    ///
+    /// The range is collected with `range_between` before anything is unlinked, so the same
+    /// errors are returned and the tree is left untouched when they are. Returns the number of
+    /// `Node`s removed.
+    pub fn delete_range(&mut self, start: &mut Node, end: &mut Node) -> DoogieResult<usize> {
+        let range = self.range_between(start, end)?;
+        let count = range.len();
+        for mut node in range {
+            node.unlink();
+        }
 
-        let result = match self {
-            Node::Document(_) => DOCUMENT_CHILDREN.contains(&child_type),
-            Node::BlockQuote(_) => BLOCK_QUOTE_CHILDREN.contains(&child_type),
-            Node::List(_) => child_type == NodeType::CMarkNodeItem,
-            Node::Item(_) => ITEM_CHILDREN.contains(&child_type),
-            Node::CodeBlock(_) => CODE_BLOCK_CHILDREN.contains(&child_type),
-            Node::HtmlBlock(_) => HTML_BLOCK_CHILDREN.contains(&child_type),
-            Node::CustomBlock(_) => CUSTOM_BLOCK_CHILDREN.contains(&child_type),
-            Node::Paragraph(_) => PARAGRAPH_CHILDREN.contains(&child_type),
-            Node::Heading(_) => HEADING_CHILDREN.contains(&child_type),
-            Node::ThematicBreak(_) => THEMATIC_BREAK_CHILDREN.contains(&child_type),
-            Node::Text(_) => TEXT_CHILDREN.contains(&child_type),
-            Node::SoftBreak(_) => SOFT_BREAK_CHILDREN.contains(&child_type),
-            Node::LineBreak(_) => LINE_BREAK_CHILDREN.contains(&child_type),
-            Node::Code(_) => CODE_CHILDREN.contains(&child_type),
-            Node::HtmlInline(_) => INLINE_HTML_CHILDREN.contains(&child_type),
-            Node::CustomInline(_) => CUSTOM_INLINE_CHILDREN.contains(&child_type),
-            Node::Emph(_) => EMPH_CHILDREN.contains(&child_type),
-            Node::Strong(_) => STRONG_CHILDREN.contains(&child_type),
-            Node::Link(_) => LINK_CHILDREN.contains(&child_type),
-            Node::Image(_) => IMAGE_CHILDREN.contains(&child_type),
-        };
+        Ok(count)
+    }
 
-        Ok(result)
+    /// Returns whether the current `Document` has no meaningful content
+    ///
+    /// A `Document` is empty if it has no children or if it holds nothing but whitespace, possibly
+    /// within paragraphs, emphasis and line breaks, as for `Item::is_empty`. A
+    /// `DoogieError::WrongNodeType` error is returned if the current `Node` is not a `Document`.
+    pub fn is_empty_document(&self) -> DoogieResult<bool> {
+        let found = self.get_cmark_type()?;
+        if found != NodeType::CMarkNodeDocument {
+            return Err(DoogieError::WrongNodeType {
+                expected: NodeType::CMarkNodeDocument,
+                found,
+            });
+        }
+
+        has_only_whitespace(self)
     }
 
-    /// Renders the document AST rooted at the current `Node` into textual CommonMark form
-    pub fn render_commonmark(&self) -> String {
-        unsafe {
-            CStr::from_ptr(cmark_render_commonmark(self.pointer(), 0))
-                .to_string_lossy()
-                .into_owned()
+    /// Returns whether the current `Node` has any children
+    ///
+    /// Unlike `first_child`, no `Node` is constructed for the child. A `Node` whose resources have
+    /// been freed has no children.
+    pub fn has_children(&self) -> bool {
+        match self.checked_pointer() {
+            Ok(pointer) => unsafe { !cmark_node_first_child(pointer).is_null() },
+            Err(_) => false,
         }
     }
 
-    /// Renders the document AST rooted at the current `Node` into textual xml form
-    pub fn render_xml(&self) -> String {
+    /// Returns the first child Node of the current `Node` if it exists
+    pub fn first_child(&self) -> DoogieResult<Option<Node>> {
+        let child_ptr: *mut CMarkNodePtr;
+        let pointer = self.checked_pointer()?;
         unsafe {
-            CStr::from_ptr(cmark_render_xml(self.pointer(), 0))
-                .to_string_lossy()
-                .into_owned()
+            child_ptr = cmark_node_first_child(pointer);
         }
-    }
 
-    /// Returns an iterator over the `Node`s of the document subtree rooted at the current `Node`
-    pub fn iter(&self) -> NodeIterator {
-        NodeIterator::new(self.pointer())
+        if child_ptr.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(Node::from_raw(child_ptr, self.manager())?))
+        }
     }
 
-    /// Returns the start line from the original CMark document corresponding to the current `Node`
-    pub fn get_start_line(&self) -> u32 {
-        unsafe { cmark_node_get_start_line(self.pointer()) as u32 }
-    }
+    /// Returns the last child Node of the current `Node` if it exists
+    pub fn last_child(&self) -> DoogieResult<Option<Node>> {
+        let child_ptr: *mut CMarkNodePtr;
+        let pointer = self.checked_pointer()?;
+        unsafe {
+            child_ptr = cmark_node_last_child(pointer);
+        }
 
-    /// Returns the start column from the original CMark document corresponding to this `Node
-    pub fn get_start_column(&self) -> u32 {
-        unsafe { cmark_node_get_start_column(self.pointer()) as u32 }
+        if child_ptr.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(Node::from_raw(child_ptr, self.manager())?))
+        }
     }
-}
 
-/// Represents the root `Node` of a document in the CommonMark AST
-pub struct Document {
-    resource: Resource,
-}
+    /// Returns the descendant of the current `Node` at the given path of child indices
+    ///
+    /// Each index selects a child of the `Node` reached so far, starting from the current `Node`,
+    /// so `at_path(&[0, 2])` is the third child of the first child. An empty path returns the
+    /// current `Node`. `None` is returned if any index is out of range.
+    pub fn at_path(&self, path: &[usize]) -> DoogieResult<Option<Node>> {
+        let mut node = self.itself()?;
+        for &index in path {
+            let mut child = node.first_child()?;
+            for _ in 0..index {
+                child = match child {
+                    Some(child) => child.next_sibling()?,
+                    None => break,
+                };
+            }
 
-impl Document {
-    /// Constructs a new `Document`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeDocument,
-                Rc::new(ResourceManager::new()),
-            ),
+            node = match child {
+                Some(child) => child,
+                None => return Ok(None),
+            };
         }
+
+        Ok(Some(node))
     }
 
-    /// Consolidates all adjacent `Text` `Node`s in the document into single `Text` `Node`s.
-    pub fn consolidate_text_nodes(&mut self) {
-        unsafe {
-            cmark_consolidate_text_nodes(self.resource.pointer);
+    /// Returns the innermost `Node` of the subtree whose source lines include the given line
+    ///
+    /// Starting from the current `Node`, the search repeatedly descends into the first child whose
+    /// start and end lines contain `line`, returning the deepest `Node` reached. When several
+    /// inlines share the line, the first of them is chosen. `None` is returned if the current
+    /// `Node` itself does not cover the line.
+    pub fn node_at_line(&self, line: u32) -> DoogieResult<Option<Node>> {
+        let covers = |node: &Node| node.get_start_line() <= line && line <= node.get_end_line();
+        if !covers(self) {
+            return Ok(None);
         }
-    }
-}
 
-/// Represents a Block Quote element in CommonMark
-pub struct BlockQuote {
-    resource: Resource,
-}
+        let mut node = self.itself()?;
+        'descend: loop {
+            let mut next = node.first_child()?;
+            while let Some(child) = next {
+                if covers(&child) {
+                    node = child;
+                    continue 'descend;
+                }
+                next = child.next_sibling()?;
+            }
 
-impl BlockQuote {
-    /// Constructs a new `BlockQuote`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeBlockQuote,
-                Rc::new(ResourceManager::new()),
-            ),
+            return Ok(Some(node));
         }
     }
-}
-
-/// Represents a List element in CommonMark
-///
-/// Lists are meta-containers in that they are classified as container blocks in CommonMark, but can
-/// only contain `Item` elements as children.
-pub struct List {
-    resource: Resource,
-}
 
-impl List {
-    /// Constructs a new `List`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeList,
-                Rc::new(ResourceManager::new()),
-            ),
+    /// Returns the first child of the current `Node` with the given type if it exists
+    pub fn first_child_of_type(&self, node_type: NodeType) -> DoogieResult<Option<Node>> {
+        let mut next = self.first_child()?;
+        while let Some(child) = next {
+            if child.get_cmark_type()? == node_type {
+                return Ok(Some(child));
+            }
+            next = child.next_sibling()?;
         }
-    }
 
-    /// Returns an enum representing the type of list i.e. Bullet or Ordered
-    pub fn get_list_type(&self) -> DoogieResult<ListType> {
-        unsafe { ListType::try_from(cmark_node_get_list_type(self.resource.pointer) as u32) }
+        Ok(None)
     }
 
-    /// Returns the delimiter type used in the case of ordered lists.
-    pub fn get_delim_type(&self) -> DoogieResult<DelimType> {
-        unsafe { DelimType::try_from(cmark_node_get_list_delim(self.resource.pointer) as u32) }
+    /// Returns the number of children of the current `Node` with the given type
+    ///
+    /// Only the immediate children are counted, not their descendants.
+    pub fn count_children_of_type(&self, node_type: NodeType) -> DoogieResult<usize> {
+        let mut count = 0;
+        let mut next = self.first_child()?;
+        while let Some(child) = next {
+            if child.get_cmark_type()? == node_type {
+                count += 1;
+            }
+            next = child.next_sibling()?;
+        }
+
+        Ok(count)
     }
-}
 
-/// Represents a List Item in CommonMark
-pub struct Item {
-    resource: Resource,
-}
+    /// Returns the last child of the current `Node` with the given type if it exists
+    pub fn last_child_of_type(&self, node_type: NodeType) -> DoogieResult<Option<Node>> {
+        let mut prev = self.last_child()?;
+        while let Some(child) = prev {
+            if child.get_cmark_type()? == node_type {
+                return Ok(Some(child));
+            }
+            prev = child.prev_sibling()?;
+        }
 
-impl Item {
-    /// Constructs a new `Item`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeItem,
-                Rc::new(ResourceManager::new()),
-            ),
+        Ok(None)
+    }
+
+    /// Sets the literal content of the current `Node` to an empty string
+    ///
+    /// This applies to `Text`, `Code`, `CodeBlock`, `HtmlBlock` and `HtmlInline` Nodes, which
+    /// remain in the tree. It has no effect on `Node`s of other types, which have no literal.
+    pub fn clear_content(&mut self) -> DoogieResult<()> {
+        match *self {
+            Node::Text(_)
+            | Node::Code(_)
+            | Node::CodeBlock(_)
+            | Node::HtmlBlock(_)
+            | Node::HtmlInline(_) => {
+                let empty = CString::new("")?;
+                unsafe {
+                    check_return_code(
+                        cmark_node_set_literal(self.checked_pointer()?, empty.as_ptr()),
+                        "clear_content",
+                    )
+                }
+            }
+            _ => Ok(()),
         }
     }
-}
 
-/// Represents a Code Block in CommonMark
-pub struct CodeBlock {
-    resource: Resource,
-}
+    /// Returns the children of the current `Node` that are blocks, in document order
+    ///
+    /// For a `Document` these are all of its children, while the children of inline `Node`s are
+    /// never blocks.
+    pub fn blocks(&self) -> DoogieResult<Vec<Node>> {
+        let mut blocks = self.children()?;
+        blocks.retain(|child| child.is_block());
 
-impl CodeBlock {
-    /// Constructs a new `CodeBlock`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeCodeBlock,
-                Rc::new(ResourceManager::new()),
-            ),
-        }
+        Ok(blocks)
     }
 
-    /// Returns the info text in the case of a Fenced Code Block
-    pub fn get_fence_info(&self) -> DoogieResult<String> {
-        unsafe {
-            Ok(
-                CStr::from_ptr(cmark_node_get_fence_info(self.resource.pointer))
-                    .to_str()?
-                    .to_string(),
-            )
+    /// Returns whether the current `Node` is a block other than a `Document`
+    fn is_block(&self) -> bool {
+        match *self {
+            Node::BlockQuote(_)
+            | Node::List(_)
+            | Node::Item(_)
+            | Node::CodeBlock(_)
+            | Node::HtmlBlock(_)
+            | Node::CustomBlock(_)
+            | Node::Paragraph(_)
+            | Node::Heading(_)
+            | Node::ThematicBreak(_) => true,
+            _ => false,
         }
     }
 
-    /// Sets the info text for the code block
-    pub fn set_fence_info(&mut self, info: &String) -> DoogieResult<u32> {
-        let info = CString::new(info.as_bytes())?;
-        let result: i32;
-        unsafe {
-            result = cmark_node_set_fence_info(self.resource.pointer, info.as_ptr());
+    /// Returns each block in the subtree along with its inner text, in document order
+    ///
+    /// The text of each block is the same as its `inner_text`, but the texts of all of the blocks
+    /// are gathered in a single traversal of the subtree. The current `Node` is included if it is
+    /// a block, while a `Document` is not.
+    pub fn blocks_with_text(&self) -> DoogieResult<Vec<(Node, String)>> {
+        let mut blocks: Vec<(Node, String)> = Vec::new();
+        let mut open = Vec::new();
+        for (node, event) in self.iter() {
+            let entered = event == IterEventType::Enter && node.is_block();
+            if entered {
+                open.push(blocks.len());
+                blocks.push((node.itself()?, String::new()));
+            }
+            for &index in &open {
+                push_inner_text(&mut blocks[index].1, &node, &event)?;
+            }
+
+            let leaf = !node.get_cmark_type()?.is_container();
+            if (entered && leaf) || (event == IterEventType::Exit && node.is_block()) {
+                open.pop();
+            }
         }
 
-        match result {
-            1 => Ok(1),
-            err => Err(DoogieError::ReturnCode(err as u32)),
+        for &mut (_, ref mut text) in &mut blocks {
+            let len = text.trim_right_matches('\n').len();
+            text.truncate(len);
         }
+
+        Ok(blocks)
     }
 
-    /// Returns the textual content of the current Code Block element
-    pub fn get_content(&self) -> DoogieResult<String> {
-        let result;
-        unsafe {
-            result = cmark_node_get_literal(self.resource.pointer);
+    /// Returns the number of `Node`s in the subtree rooted at the current `Node`
+    ///
+    /// The current `Node` is included in the count, so a `Node` without children has a size of 1.
+    pub fn subtree_size(&self) -> usize {
+        self.iter()
+            .filter(|&(_, ref event)| *event == IterEventType::Enter)
+            .count()
+    }
+
+    /// Returns the greatest depth of any descendant below the current `Node`
+    ///
+    /// Children have a depth of 1, so a `Node` without children has a height of 0.
+    pub fn height(&self) -> usize {
+        let mut open = 0;
+        let mut height = 0;
+        for (node, event) in self.iter() {
+            match event {
+                IterEventType::Enter => {
+                    height = std::cmp::max(height, open);
+                    let container = node
+                        .get_cmark_type()
+                        .map(|node_type| node_type.is_container())
+                        .unwrap_or(false);
+                    if container {
+                        open += 1;
+                    }
+                }
+                IterEventType::Exit => open -= 1,
+                _ => (),
+            }
         }
 
-        if result.is_null() {
-            return Ok(String::new());
-        } else {
-            unsafe {
-                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+        height
+    }
+
+    /// Returns whether any descendant of the current `Node` is nested deeper than `limit`
+    ///
+    /// Depths are counted as for `height`, but the traversal stops at the first `Node` beyond the
+    /// limit. This allows rejecting adversarial documents, such as thousands of nested block
+    /// quotes, without walking all of them.
+    pub fn max_depth_exceeded(&self, limit: usize) -> DoogieResult<bool> {
+        let mut open = 0;
+        for (node, event) in self.iter() {
+            match event {
+                IterEventType::Enter => {
+                    if open > limit {
+                        return Ok(true);
+                    }
+                    if node.get_cmark_type()?.is_container() {
+                        open += 1;
+                    }
+                }
+                IterEventType::Exit => open -= 1,
+                _ => (),
             }
         }
+
+        Ok(false)
     }
 
-    /// Sets the textual content of the current Code Block element
-    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
-        let content = CString::new(content.as_bytes())?;
-        let result: i32;
-        unsafe {
-            result = cmark_node_set_literal(self.resource.pointer, content.as_ptr());
+    /// Returns whether the subtree rooted at the current `Node` contains a `Node` of the given type
+    ///
+    /// The traversal stops at the first match.
+    pub fn contains_type(&self, node_type: NodeType) -> DoogieResult<bool> {
+        for (node, _) in self.iter() {
+            if node.get_cmark_type()? == node_type {
+                return Ok(true);
+            }
         }
 
-        match result {
-            1 => Ok(1 as u32),
-            i => Err(DoogieError::ReturnCode(i as u32)),
+        Ok(false)
+    }
+
+    /// Returns the inner text of each `Node` of the given type in the subtree, in document order
+    ///
+    /// The current `Node` is included when it has the given type. A matching `Node` nested
+    /// inside another one contributes its own text in addition to being part of the outer text.
+    pub fn text_in_type(&self, node_type: NodeType) -> DoogieResult<Vec<String>> {
+        let mut texts = Vec::new();
+        for (node, event) in self.iter() {
+            if event == IterEventType::Enter && node.get_cmark_type()? == node_type {
+                texts.push(node.inner_text()?);
+            }
         }
+
+        Ok(texts)
     }
-}
 
-/// Represents a block of HTML in CommonMark
-pub struct HtmlBlock {
-    resource: Resource,
-}
+    /// Returns the concatenated textual content of the document subtree rooted at the current
+    /// `Node`
+    ///
+    /// Only the content of `Text` and inline `Code` nodes is collected. Soft breaks become spaces,
+    /// line breaks become newlines and the contents of separate blocks are separated by newlines.
+    pub fn inner_text(&self) -> DoogieResult<String> {
+        let mut buffer = String::new();
+        for (node, event) in self.iter() {
+            push_inner_text(&mut buffer, &node, &event)?;
+        }
 
-impl HtmlBlock {
-    /// Constructs a new `HtmlBlock`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeHtmlBlock,
-                Rc::new(ResourceManager::new()),
-            ),
-        }
+        Ok(buffer.trim_right_matches('\n').to_string())
     }
-}
 
-/// Represents an ambiguous Block Element
-pub struct CustomBlock {
-    resource: Resource,
-}
+    /// Returns the contents of every `CodeBlock` in the subtree whose language is `lang`
+    ///
+    /// The language is the first token of the fence info, so extra attributes following the
+    /// language and separated from it by whitespace or a comma (e.g. `rust,ignore`) are ignored.
+    pub fn code_blocks_for_language(&self, lang: &str) -> DoogieResult<Vec<String>> {
+        self.code_blocks(Some(lang))
+    }
 
-impl CustomBlock {
-    /// Constructs a new `CustomBlock`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeCustomBlock,
-                Rc::new(ResourceManager::new()),
-            ),
+    /// Returns the contents of every `CodeBlock` in the subtree joined into a single string
+    ///
+    /// If `lang` is given, only the code blocks whose language is `lang` are included, as with
+    /// `code_blocks_for_language`. The contents are joined in document order, each starting on a
+    /// new line.
+    pub fn concat_code_blocks(&self, lang: Option<&str>) -> DoogieResult<String> {
+        let mut code = String::new();
+        for block in self.code_blocks(lang)? {
+            if !code.is_empty() && !code.ends_with('\n') {
+                code.push('\n');
+            }
+            code.push_str(&block);
         }
+
+        Ok(code)
     }
-}
 
-/// Represents a Paragraph element in CommonMark
-pub struct Paragraph {
-    resource: Resource,
-}
+    /// Returns the contents of every `CodeBlock` in the subtree, optionally filtered by language
+    fn code_blocks(&self, lang: Option<&str>) -> DoogieResult<Vec<String>> {
+        let mut blocks = Vec::new();
+        for (node, event) in self.iter() {
+            if let (Node::CodeBlock(block), IterEventType::Enter) = (node, event) {
+                let info = block.get_fence_info()?;
+                let language = info
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .next()
+                    .unwrap_or("");
+
+                if lang.map_or(true, |lang| language == lang) {
+                    blocks.push(block.get_content()?);
+                }
+            }
+        }
 
-impl Paragraph {
-    /// Constructs a new `Paragraph`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeParagraph,
-                Rc::new(ResourceManager::new()),
-            ),
+        Ok(blocks)
+    }
+
+    /// Resolves the relative URLs of every `Link` and `Image` in the subtree against `base`
+    ///
+    /// URLs with a scheme (e.g. `https:` or `mailto:`), protocol relative URLs and fragment only
+    /// URLs such as `#usage` are left untouched. Returns the number of URLs that were changed.
+    pub fn rebase_links(&mut self, base: &str) -> DoogieResult<usize> {
+        let mut count = 0;
+        for (node, event) in self.iter() {
+            if event != IterEventType::Enter {
+                continue;
+            }
+
+            let changed = match node {
+                Node::Link(mut link) => {
+                    let url = link.get_url()?;
+                    match rebase_url(base, &url) {
+                        Some(rebased) => link.set_url(&rebased).map(|_| true)?,
+                        None => false,
+                    }
+                }
+                Node::Image(mut image) => {
+                    let url = image.get_url()?;
+                    match rebase_url(base, &url) {
+                        Some(rebased) => image.set_url(&rebased).map(|_| true)?,
+                        None => false,
+                    }
+                }
+                _ => false,
+            };
+
+            if changed {
+                count += 1;
+            }
         }
+
+        Ok(count)
     }
-}
 
-/// Represents a Heading element in CommonMark
-pub struct Heading {
-    resource: Resource,
-}
+    /// Returns the labels of reference links in the subtree that have no matching definition
+    ///
+    /// libcmark leaves reference links (`[text][ref]`, `[text][]` and `[text]`) without a
+    /// definition in the document as literal text, so these are found by scanning each run of
+    /// adjacent `Text` and `SoftBreak` siblings for bracketed labels. Brackets that were escaped
+    /// in the source are not treated as labels, nor are bracketed texts followed by `(`, nor
+    /// `[text]` directly after a letter or digit, as in `array[0]`. Labels that contain other
+    /// inline elements, such as emphasis, are not detected. Each label is returned once, in
+    /// document order.
+    ///
+    /// Any other bracketed text is reported even if it was never meant as a link, such as a task
+    /// list marker (`[x]`) or an editorial note (`[sic]`).
+    pub fn find_undefined_references(&self) -> DoogieResult<Vec<String>> {
+        let mut labels = Vec::new();
+        for (node, event) in self.iter() {
+            if event != IterEventType::Enter {
+                continue;
+            }
 
-impl Heading {
-    /// Constructs a new `Heading`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeHeading,
-                Rc::new(ResourceManager::new()),
-            ),
+            let mut run = Vec::new();
+            let mut next = node.first_child()?;
+            while let Some(child) = next {
+                next = child.next_sibling()?;
+                let in_run = match child {
+                    Node::Text(ref text) => {
+                        let content = text.get_content()?;
+                        let escaped = (content == "[" || content == "]")
+                            && child.get_start_line() == child.get_end_line()
+                            && child.get_end_column() > child.get_start_column();
+                        run.extend(content.chars().map(|c| (c, escaped)));
+                        true
+                    }
+                    Node::SoftBreak(_) => {
+                        run.push((' ', false));
+                        true
+                    }
+                    _ => false,
+                };
+                if in_run && next.is_some() {
+                    continue;
+                }
+
+                for label in bracketed_labels(&run) {
+                    if !labels.contains(&label) {
+                        labels.push(label);
+                    }
+                }
+                run.clear();
+            }
         }
+
+        Ok(labels)
     }
 
-    /// Returns the heading level of the current Heading
-    pub fn get_level(&self) -> usize {
-        unsafe { cmark_node_get_heading_level(self.resource.pointer) as usize }
+    /// Returns the first `Heading` in the subtree whose text matches the given text
+    ///
+    /// The inner text of each heading is compared with `text` after trimming surrounding
+    /// whitespace from both. The comparison is exact and so is case sensitive.
+    pub fn find_heading(&self, text: &str) -> DoogieResult<Option<Node>> {
+        for (node, event) in self.iter() {
+            if let (&Node::Heading(_), IterEventType::Enter) = (&node, event) {
+                if node.inner_text()?.trim() == text.trim() {
+                    return Ok(Some(node));
+                }
+            }
+        }
+
+        Ok(None)
     }
-}
 
-/// Represents a Thematic Break element in CommonMark
-pub struct ThematicBreak {
-    resource: Resource,
-}
+    /// Returns the number of words of prose in the subtree rooted at the current `Node`
+    ///
+    /// Words are the whitespace separated parts of the `inner_text`, so inline code and the alt
+    /// text of images are counted while code blocks and raw HTML are not.
+    pub fn word_count(&self) -> DoogieResult<usize> {
+        Ok(self.inner_text()?.split_whitespace().count())
+    }
 
-impl ThematicBreak {
-    /// Constructs a new `ThematicBreak`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeThematicBreak,
-                Rc::new(ResourceManager::new()),
-            ),
+    /// Returns the estimated time needed to read the subtree rooted at the current `Node`
+    ///
+    /// The estimate is the `word_count` divided by the given reading speed in words per minute.
+    /// A `DoogieError::InvalidArgument` error is returned if the reading speed is zero.
+    pub fn reading_time(&self, words_per_minute: usize) -> DoogieResult<Duration> {
+        if words_per_minute == 0 {
+            return Err(DoogieError::InvalidArgument(
+                "words_per_minute must be greater than zero".to_string(),
+            ));
         }
+
+        let millis = self.word_count()? as u64 * 60_000 / words_per_minute as u64;
+        Ok(Duration::from_millis(millis))
     }
-}
 
-/// Represents a Text element in CommonMark
-pub struct Text {
-    resource: Resource,
-}
+    /// Returns summary statistics of the subtree rooted at the current `Node`
+    ///
+    /// All of the statistics are gathered in a single walk of the subtree, which is cheaper than
+    /// calling `word_count` and counting each type of `Node` separately.
+    pub fn statistics(&self) -> DoogieResult<DocStats> {
+        let mut stats = DocStats::default();
+        let mut buffer = String::new();
+        for (node, event) in self.iter() {
+            push_inner_text(&mut buffer, &node, &event)?;
+            if event != IterEventType::Enter {
+                continue;
+            }
 
-impl Text {
-    /// Constructs a new `Text`
-    pub fn new() -> Self {
-        Text {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeText,
-                Rc::new(ResourceManager::new()),
-            ),
+            match node {
+                Node::Heading(_) => stats.headings += 1,
+                Node::Link(_) => stats.links += 1,
+                Node::Image(_) => stats.images += 1,
+                Node::CodeBlock(_) => stats.code_blocks += 1,
+                _ => (),
+            }
         }
+
+        let text = buffer.trim_right_matches('\n');
+        stats.words = text.split_whitespace().count();
+        stats.characters = text.chars().count();
+
+        Ok(stats)
     }
 
-    /// Returns the textual content of the current Text element
-    pub fn get_content(&self) -> DoogieResult<String> {
-        let result;
-        unsafe {
-            result = cmark_node_get_literal(self.resource.pointer);
+    /// Returns the hierarchical outline of the headings in the subtree rooted at the current `Node`
+    ///
+    /// Each heading is nested under the closest preceding heading with a lower level, so skipped
+    /// levels are tolerated: a level 3 heading following a level 1 heading becomes its child.
+    /// Headings without such a predecessor are returned at the top level.
+    pub fn outline(&self) -> DoogieResult<Vec<OutlineNode>> {
+        fn attach(roots: &mut Vec<OutlineNode>, stack: &mut Vec<OutlineNode>, node: OutlineNode) {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => roots.push(node),
+            }
         }
 
-        if result.is_null() {
-            return Ok(String::new());
-        } else {
-            unsafe {
-                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+        let mut roots = Vec::new();
+        let mut stack: Vec<OutlineNode> = Vec::new();
+        for (node, event) in self.iter() {
+            let level = match (&node, event) {
+                (&Node::Heading(ref heading), IterEventType::Enter) => heading.get_level(),
+                _ => continue,
+            };
+
+            while stack.last().map_or(false, |top| top.level >= level) {
+                let done = stack.pop().unwrap();
+                attach(&mut roots, &mut stack, done);
             }
+            stack.push(OutlineNode {
+                level,
+                title: node.inner_text()?,
+                children: Vec::new(),
+            });
         }
-    }
 
-    /// Sets the textual content of the current Text element
-    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
-        let content = CString::new(content.as_bytes())?;
-        let result: i32;
-        unsafe {
-            result = cmark_node_set_literal(self.resource.pointer, content.as_ptr());
+        while let Some(done) = stack.pop() {
+            attach(&mut roots, &mut stack, done);
         }
 
-        match result {
-            1 => Ok(1 as u32),
-            i => Err(DoogieError::ReturnCode(i as u32)),
-        }
+        Ok(roots)
     }
-}
 
-/// Represents a Soft Break element in CommonMark
-pub struct SoftBreak {
-    resource: Resource,
-}
+    /// Prepends an HTML anchor to every `Heading` in the subtree rooted at the current `Node`
+    ///
+    /// The anchor is an `HtmlInline` built from `template` by replacing each `{slug}` placeholder
+    /// with a slug of the heading text, and it becomes the first child of the heading. Slugs are
+    /// lowercase, with whitespace replaced by hyphens and other punctuation removed. Repeated
+    /// slugs are made unique by appending `-1`, `-2` and so on. Returns the number of anchors that
+    /// were added.
+    pub fn add_heading_anchors(&mut self, template: &str) -> DoogieResult<usize> {
+        let mut headings = Vec::new();
+        for (node, event) in self.iter() {
+            if let (&Node::Heading(_), IterEventType::Enter) = (&node, event) {
+                headings.push(node);
+            }
+        }
 
-impl SoftBreak {
-    /// Constructs a new `SoftBreak`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeSoftbreak,
-                Rc::new(ResourceManager::new()),
-            ),
+        let mut slugs: Vec<String> = Vec::new();
+        for heading in headings.iter_mut() {
+            let base = slugify(&heading.inner_text()?);
+            let mut slug = base.clone();
+            let mut suffix = 0;
+            while slugs.contains(&slug) {
+                suffix += 1;
+                slug = format!("{}-{}", base, suffix);
+            }
+
+            let mut anchor = HtmlInline::new();
+            anchor.set_content(&template.replace("{slug}", &slug))?;
+            heading.insert_first_child(&mut Node::HtmlInline(anchor))?;
+            slugs.push(slug);
         }
+
+        Ok(slugs.len())
     }
-}
 
-/// Represents a Line Break element in CommonMark
-pub struct LineBreak {
-    resource: Resource,
-}
+    /// Returns the section of the current `Node` introduced by the heading with the given text
+    ///
+    /// Only `Heading`s that are direct children of the current `Node` are considered and the text
+    /// of the heading must match exactly, ignoring surrounding whitespace. The section is a copy
+    /// of the matching heading along with each of the following siblings up until the next
+    /// heading of the same or a higher level. It is returned as a new `Document`.
+    pub fn section(&self, heading_text: &str) -> DoogieResult<Option<Node>> {
+        let mut next = self.first_child()?;
+        while let Some(block) = next {
+            let level = match block {
+                Node::Heading(ref heading) => Some(heading.get_level()),
+                _ => None,
+            };
+
+            if let Some(level) = level {
+                if block.inner_text()?.trim() == heading_text.trim() {
+                    let mut section = Node::from_type(NodeType::CMarkNodeDocument)?;
+                    section.manager().track_root(&section.pointer());
+                    section.append_child(&mut block.deep_copy()?)?;
+
+                    for sibling in block.following_siblings() {
+                        if let Node::Heading(ref heading) = sibling {
+                            if heading.get_level() <= level {
+                                break;
+                            }
+                        }
+                        section.append_child(&mut sibling.deep_copy()?)?;
+                    }
 
-impl LineBreak {
-    /// Constructs a new `LineBreak`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeLinebreak,
-                Rc::new(ResourceManager::new()),
-            ),
+                    return Ok(Some(section));
+                }
+            }
+
+            next = block.next_sibling()?;
         }
-    }
-}
 
-/// Represents an inline Code element in CommonMark
-pub struct Code {
-    resource: Resource,
-}
+        Ok(None)
+    }
 
-impl Code {
-    /// Constructs a new `Code`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeCode,
-                Rc::new(ResourceManager::new()),
-            ),
+    /// Returns a copy of the document subtree rooted at the current `Node`
+    ///
+    /// The copy is not linked into any document and owns its own memory, so it remains valid
+    /// independently of the original.
+    pub fn deep_copy(&self) -> DoogieResult<Node> {
+        let mut copy = Node::from_type(self.get_cmark_type()?)?;
+        copy.copy_attributes(self)?;
+        copy.manager().track_root(&copy.pointer());
+
+        let mut next = self.first_child()?;
+        while let Some(child) = next {
+            copy.append_child(&mut child.deep_copy()?)?;
+            next = child.next_sibling()?;
         }
+
+        Ok(copy)
     }
 
-    /// Returns the textual content of the current Text element
-    pub fn get_content(&self) -> DoogieResult<String> {
-        let result;
-        unsafe {
-            result = cmark_node_get_literal(self.resource.pointer);
+    /// Returns a copy of the subtree rooted at the current `Node` as a standalone `Document`
+    ///
+    /// The `Document` has its own `ResourceManager`, so it remains valid after the original
+    /// document is dropped. A `Document` is copied as is, while other `Node`s are placed in a new
+    /// `Document`, wrapped in a `Paragraph` if they are inlines or in a `List` if they are `Item`s
+    /// so that the result is a valid CommonMark tree.
+    pub fn to_owned_document(&self) -> DoogieResult<Node> {
+        let node_type = self.get_cmark_type()?;
+        if node_type == NodeType::CMarkNodeDocument {
+            return self.deep_copy();
         }
 
-        if result.is_null() {
-            return Ok(String::new());
+        let mut document = Node::from_type(NodeType::CMarkNodeDocument)?;
+        document.manager().track_root(&document.pointer());
+
+        let mut copy = self.deep_copy()?;
+        if DOCUMENT_CHILDREN.contains(&node_type) {
+            document.append_child(&mut copy)?;
         } else {
-            unsafe {
-                return Ok(CStr::from_ptr(result).to_str()?.to_string());
-            }
+            let mut wrapper = match node_type {
+                NodeType::CMarkNodeItem => Node::from_type(NodeType::CMarkNodeList)?,
+                _ => Node::from_type(NodeType::CMarkNodeParagraph)?,
+            };
+            wrapper.append_child(&mut copy)?;
+            document.append_child(&mut wrapper)?;
         }
+
+        Ok(document)
     }
 
-    /// Sets the textual content of the current Text element
-    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
-        let content = CString::new(content.as_bytes())?;
-        let result: i32;
+    /// Copies the type specific attributes of the given `Node` onto the current `Node`
+    fn copy_attributes(&mut self, source: &Node) -> DoogieResult<()> {
+        let from = source.pointer();
+        let to = self.pointer();
+        let op = "deep_copy";
         unsafe {
-            result = cmark_node_set_literal(self.resource.pointer, content.as_ptr());
+            match source.get_cmark_type()? {
+                NodeType::CMarkNodeText
+                | NodeType::CMarkNodeCode
+                | NodeType::CMarkNodeHtmlBlock
+                | NodeType::CMarkNodeHtmlInline => {
+                    check_return_code(cmark_node_set_literal(to, cmark_node_get_literal(from)), op)
+                }
+                NodeType::CMarkNodeCodeBlock => {
+                    check_return_code(
+                        cmark_node_set_literal(to, cmark_node_get_literal(from)),
+                        op,
+                    )?;
+                    check_return_code(
+                        cmark_node_set_fence_info(to, cmark_node_get_fence_info(from)),
+                        op,
+                    )
+                }
+                NodeType::CMarkNodeHeading => check_return_code(
+                    cmark_node_set_heading_level(to, cmark_node_get_heading_level(from)),
+                    op,
+                ),
+                NodeType::CMarkNodeList => {
+                    check_return_code(
+                        cmark_node_set_list_type(to, cmark_node_get_list_type(from)),
+                        op,
+                    )?;
+                    check_return_code(
+                        cmark_node_set_list_delim(to, cmark_node_get_list_delim(from)),
+                        op,
+                    )?;
+                    check_return_code(
+                        cmark_node_set_list_start(to, cmark_node_get_list_start(from)),
+                        op,
+                    )?;
+                    check_return_code(
+                        cmark_node_set_list_tight(to, cmark_node_get_list_tight(from)),
+                        op,
+                    )
+                }
+                NodeType::CMarkNodeLink | NodeType::CMarkNodeImage => {
+                    check_return_code(cmark_node_set_url(to, cmark_node_get_url(from)), op)?;
+                    check_return_code(cmark_node_set_title(to, cmark_node_get_title(from)), op)
+                }
+                NodeType::CMarkNodeCustomBlock | NodeType::CMarkNodeCustomInline => {
+                    check_return_code(
+                        cmark_node_set_on_enter(to, cmark_node_get_on_enter(from)),
+                        op,
+                    )?;
+                    check_return_code(cmark_node_set_on_exit(to, cmark_node_get_on_exit(from)), op)
+                }
+                _ => Ok(()),
+            }
         }
+    }
 
-        match result {
-            1 => Ok(1 as u32),
-            i => Err(DoogieError::ReturnCode(i as u32)),
+    /// Shifts the level of every `Heading` in the subtree rooted at the current `Node` by `delta`
+    ///
+    /// A positive delta demotes headings and a negative delta promotes them. Levels that would
+    /// fall outside of the range 1 to 6 supported by CommonMark are clamped to that range rather
+    /// than treated as an error, so shifting is lossy for headings pushed past either end.
+    pub fn shift_headings(&mut self, delta: i32) -> DoogieResult<()> {
+        for (node, event) in self.iter() {
+            if let (Node::Heading(ref mut heading), IterEventType::Enter) = (node, event) {
+                let level = heading.get_level() as i32 + delta;
+                heading.set_level(std::cmp::max(1, std::cmp::min(6, level)) as usize)?;
+            }
         }
+
+        Ok(())
     }
-}
 
-/// Represents an inline HTML element in CommonMark
-pub struct HtmlInline {
-    resource: Resource,
-}
+    /// Removes every descendant of the current `Node` for which `pred` returns false
+    ///
+    /// The `Node`s are visited in document order and the predicate is not called for the
+    /// descendants of a removed `Node`, which are removed along with it. The current `Node` itself
+    /// is always kept. Returns the number of `Node`s for which the predicate returned false.
+    pub fn retain<F>(&mut self, pred: F) -> DoogieResult<usize>
+    where
+        F: Fn(&Node) -> bool,
+    {
+        let mut removed = 0;
+        let mut pending = self.children()?;
+        pending.reverse();
+
+        while let Some(mut node) = pending.pop() {
+            if pred(&node) {
+                let mut children = node.children()?;
+                children.reverse();
+                pending.append(&mut children);
+            } else {
+                node.unlink();
+                removed += 1;
+            }
+        }
 
-impl HtmlInline {
-    /// Constructs a new `HtmlInline`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeHtmlInline,
-                Rc::new(ResourceManager::new()),
-            ),
+        Ok(removed)
+    }
+
+    /// Removes the empty descendants of the current `Node`
+    ///
+    /// Containers without children and `Text` whose content is empty or only whitespace are
+    /// removed. Descendants are visited before their ancestors, so a container left empty by the
+    /// removal of its children is removed as well. `Image`s, whose children are optional alt text,
+    /// and the current `Node` itself are always kept. Returns the number of `Node`s removed.
+    pub fn prune_empty(&mut self) -> DoogieResult<usize> {
+        let mut removed = 0;
+        for mut node in self
+            .iter_rev()
+            .filter(|node| node.pointer() != self.pointer())
+        {
+            let empty = match node {
+                Node::Text(ref text) => text.get_content()?.trim().is_empty(),
+                Node::BlockQuote(_)
+                | Node::List(_)
+                | Node::Item(_)
+                | Node::Paragraph(_)
+                | Node::Heading(_)
+                | Node::Emph(_)
+                | Node::Strong(_)
+                | Node::Link(_) => node.first_child()?.is_none(),
+                _ => false,
+            };
+
+            if empty {
+                node.unlink();
+                removed += 1;
+            }
         }
+
+        Ok(removed)
     }
-}
 
-/// Represents an ambiguous inline element
-pub struct CustomInline {
-    resource: Resource,
-}
+    /// Replaces the content of each `Text` in the subtree rooted at the current `Node` with the
+    /// result of applying `f` to it
+    ///
+    /// The `Text` Nodes are visited in document order. Returns the number of `Text` Nodes visited.
+    pub fn map_text<F>(&mut self, mut f: F) -> DoogieResult<usize>
+    where
+        F: FnMut(&str) -> String,
+    {
+        let mut count = 0;
+        for (node, event) in self.iter() {
+            if let (Node::Text(mut text), IterEventType::Enter) = (node, event) {
+                let content = f(&text.get_content()?);
+                text.set_content(&content)?;
+                count += 1;
+            }
+        }
 
-impl CustomInline {
-    /// Constructs a new `CustomInline`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeCustomInline,
-                Rc::new(ResourceManager::new()),
-            ),
-        }
+        Ok(count)
     }
-}
 
-/// Represenets an Emph element in CommonMark
-pub struct Emph {
-    resource: Resource,
-}
+    /// Collapses each run of whitespace within the `Text` of the subtree rooted at the current
+    /// `Node` to a single space
+    ///
+    /// Leading and trailing whitespace is collapsed rather than removed, since it separates the
+    /// `Text` from neighbouring inlines. The content of `Code` and `CodeBlock` Nodes is left
+    /// untouched. Returns the number of `Text` Nodes that were changed.
+    pub fn normalize_text(&mut self) -> DoogieResult<usize> {
+        let mut changed = 0;
+        for (node, event) in self.iter() {
+            if let (Node::Text(mut text), IterEventType::Enter) = (node, event) {
+                let content = text.get_content()?;
+                let mut normalized = String::with_capacity(content.len());
+                for c in content.chars() {
+                    if !c.is_whitespace() {
+                        normalized.push(c);
+                    } else if !normalized.ends_with(' ') {
+                        normalized.push(' ');
+                    }
+                }
 
-impl Emph {
-    /// Constructs a new `Emph`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeEmph,
-                Rc::new(ResourceManager::new()),
-            ),
+                if normalized != content {
+                    text.set_content(&normalized)?;
+                    changed += 1;
+                }
+            }
         }
+
+        Ok(changed)
     }
-}
 
-/// Represents a Strong element in CommonMark
-pub struct Strong {
-    resource: Resource,
-}
+    /// Decodes the HTML character references within the `Text` of the subtree rooted at the
+    /// current `Node` with `decode_entities`
+    ///
+    /// libcmark already decodes the references in the source of a parsed document, so this mostly
+    /// affects content that was set programmatically. Returns the number of `Text` Nodes that were
+    /// changed.
+    pub fn decode_text_entities(&mut self) -> DoogieResult<usize> {
+        let mut changed = 0;
+        for (node, event) in self.iter() {
+            if let (Node::Text(mut text), IterEventType::Enter) = (node, event) {
+                let content = text.get_content()?;
+                let decoded = decode_entities(&content);
+                if decoded != content {
+                    text.set_content(&decoded)?;
+                    changed += 1;
+                }
+            }
+        }
 
-impl Strong {
-    /// Constructs a new `Strong`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeStrong,
-                Rc::new(ResourceManager::new()),
-            ),
+        Ok(changed)
+    }
+
+    /// Removes every `HtmlBlock` and `HtmlInline` from the subtree rooted at the current `Node`
+    ///
+    /// This sanitizes the raw HTML of untrusted documents regardless of how the tree is rendered
+    /// afterwards. Returns the number of `Node`s removed.
+    pub fn strip_html(&mut self) -> DoogieResult<usize> {
+        self.retain(|node| match *node {
+            Node::HtmlBlock(_) | Node::HtmlInline(_) => false,
+            _ => true,
+        })
+    }
+
+    /// Replaces each `Link` in the subtree rooted at the current `Node` with its children
+    ///
+    /// The text of the links is kept in place while their destinations and titles are dropped.
+    /// `Image`s are left untouched. Returns the number of links replaced.
+    pub fn flatten_links(&mut self) -> DoogieResult<usize> {
+        let links: Vec<Node> = self
+            .iter()
+            .filter(|&(ref node, ref event)| match (node, event) {
+                (&Node::Link(_), &IterEventType::Enter) => true,
+                _ => false,
+            })
+            .map(|(node, _)| node)
+            .collect();
+
+        let count = links.len();
+        for mut link in links {
+            link.unwrap()?;
         }
+
+        Ok(count)
     }
-}
 
-/// Represents a Link element in CommonMark
-pub struct Link {
-    resource: Resource,
-}
+    /// Merges each `List` in the subtree into the preceding sibling `List` of the same kind
+    ///
+    /// Two consecutive sibling lists are merged when they have the same list type and, for
+    /// ordered lists, the same delimiter. The items of the second list are moved to the end of the
+    /// first, which becomes loose if either list was loose, and the second list is unlinked.
+    /// Runs of several such lists are merged into the first of them. Returns the number of
+    /// lists removed.
+    pub fn merge_adjacent_lists(&mut self) -> DoogieResult<usize> {
+        let mut merged = 0;
+        for mut node in self.iter_rev() {
+            let mut next = match (node.as_list(), node.next_sibling()?) {
+                (Some(_), Some(next)) => next,
+                _ => continue,
+            };
+
+            let tight = match (node.as_list(), next.as_list()) {
+                (Some(list), Some(next_list)) => {
+                    if list.get_list_type()? != next_list.get_list_type()?
+                        || list.get_delim_type()? != next_list.get_delim_type()?
+                    {
+                        continue;
+                    }
+                    list.get_list_tight() && next_list.get_list_tight()
+                }
+                _ => continue,
+            };
+            for mut item in next.children()? {
+                node.append_child(&mut item)?;
+            }
+            next.unlink();
+            if let Some(list) = node.as_list_mut() {
+                list.set_list_tight(tight)?;
+            }
+            merged += 1;
+        }
 
-impl Link {
-    /// Constructs a new `Link`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeLink,
-                Rc::new(ResourceManager::new()),
-            ),
+        Ok(merged)
+    }
+
+    /// Turns the bare `http://` and `https://` URLs in the text of the subtree into `Link`s
+    ///
+    /// libcmark splits text at characters that may start inline markup, so each run of adjacent
+    /// `Text` siblings is scanned as a whole. A run that contains URLs is replaced by `Text`s for
+    /// the surrounding text and a `Link` for each URL, whose text is the URL itself. Text within
+    /// `Link`s and `Image`s is left untouched. See `find_urls` for how URLs are delimited. Returns
+    /// the number of links created.
+    pub fn autolink_text(&mut self) -> DoogieResult<usize> {
+        let mut runs = Vec::new();
+        let mut link_depth = 0;
+        for (node, event) in self.iter() {
+            match (&node, &event) {
+                (&Node::Link(_), &IterEventType::Enter)
+                | (&Node::Image(_), &IterEventType::Enter) => link_depth += 1,
+                (&Node::Link(_), &IterEventType::Exit)
+                | (&Node::Image(_), &IterEventType::Exit) => link_depth -= 1,
+                (_, &IterEventType::Enter) if link_depth == 0 => {
+                    let mut run: Vec<Node> = Vec::new();
+                    let mut content = String::new();
+                    for child in node.children()? {
+                        if let Node::Text(ref text) = child {
+                            content.push_str(&text.get_content()?);
+                        }
+                        if child.get_cmark_type()? == NodeType::CMarkNodeText {
+                            run.push(child);
+                        } else if !run.is_empty() {
+                            runs.push((run, content));
+                            run = Vec::new();
+                            content = String::new();
+                        }
+                    }
+                    if !run.is_empty() {
+                        runs.push((run, content));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut count = 0;
+        for (run, content) in runs {
+            let urls = find_urls(&content);
+            if urls.is_empty() {
+                continue;
+            }
+
+            let mut anchor = run[0].itself()?;
+            let mut start = 0;
+            for (url_start, url_end) in urls {
+                if url_start > start {
+                    let mut text = Text::new();
+                    text.set_content(&content[start..url_start].to_string())?;
+                    anchor.insert_sibling_before(&mut Node::Text(text))?;
+                }
+
+                let url = content[url_start..url_end].to_string();
+                let mut link = Link::new();
+                link.set_url(&url)?;
+                let mut link = Node::Link(link);
+                let mut text = Text::new();
+                text.set_content(&url)?;
+                link.append_child(&mut Node::Text(text))?;
+                anchor.insert_sibling_before(&mut link)?;
+
+                start = url_end;
+                count += 1;
+            }
+            if start < content.len() {
+                let mut text = Text::new();
+                text.set_content(&content[start..].to_string())?;
+                anchor.insert_sibling_before(&mut Node::Text(text))?;
+            }
+
+            for mut text in run {
+                text.unlink();
+            }
         }
+
+        Ok(count)
     }
 
-    /// Returns the URL portion of the Link
-    pub fn get_url(&self) -> DoogieResult<String> {
+    /// Returns a new instance of the current `Node`
+    ///
+    /// The returned `Node` will share the underlying memory resource and manager of the current Node.
+    pub fn itself(&self) -> DoogieResult<Node> {
+        Ok(Node::from_raw(self.checked_pointer()?, self.manager())?)
+    }
+
+    /// Unlinks the current `Node` from its position in the document AST
+    ///
+    /// After unlinking, the Node will have no parent or siblings, but will retain all of its
+    /// children. Unlinking a `Node` whose resources have been freed has no effect.
+    pub fn unlink(&mut self) {
+        if self.manager().is_freed() {
+            return;
+        }
+
+        let parent: *mut CMarkNodePtr;
         unsafe {
-            Ok(CStr::from_ptr(cmark_node_get_url(self.resource.pointer))
-                .to_str()?
-                .to_string())
+            parent = cmark_node_parent(self.pointer());
+            cmark_node_unlink(self.pointer());
+        }
+        self.manager().track_root(&self.pointer());
+
+        if !parent.is_null() {
+            self.manager().notify(MutationEvent::Unlink {
+                node: self.event_id(),
+                parent: parent as usize,
+            });
         }
     }
 
-    /// Returns the title portion of the Link
-    pub fn get_title(&self) -> DoogieResult<String> {
+    /// Unlinks the current `Node` and returns a `Node` that keeps its subtree alive
+    ///
+    /// The subtree remains tracked by the `ResourceManager` of the original document, which every
+    /// `Node` of the document shares, including any instances of the `Node` and its descendants
+    /// that were obtained before detaching. The subtree is therefore freed exactly once, when the
+    /// last of them has been dropped, regardless of whether the original document is dropped
+    /// first. The subtree is freed along with the rest of the document if it is closed.
+    pub fn detach(&mut self) -> DoogieResult<Node> {
+        self.checked_pointer()?;
+        self.unlink();
+
+        self.itself()
+    }
+
+    /// Registers a callback that is notified of each structural change to the tree of the
+    /// current `Node`
+    ///
+    /// The callback is shared by every `Node` of the tree and replaces any previously registered
+    /// callback. It is notified after each successful `append_child`, `prepend_child`,
+    /// `insert_before`, `insert_after` and `unlink` of a `Node` that had a parent, as well as the
+    /// insertions performed by operations such as `unwrap` and `wrap_in`. Insertions are reported
+    /// to the callback of the tree that the `Node` is inserted into.
+    pub fn observe_mutations<F>(&self, observer: F)
+    where
+        F: FnMut(MutationEvent) + 'static,
+    {
+        *self.manager().observer.borrow_mut() = Some(Box::new(observer));
+    }
+
+    /// Removes the callback registered with `observe_mutations`, if any
+    pub fn stop_observing_mutations(&self) {
+        *self.manager().observer.borrow_mut() = None;
+    }
+
+    /// Append the given `Node` as the last child of the current `Node` if possible
+    ///
+    /// The rules of the CommonMark AST must be respected when appending nodes. Not all Nodes can
+    /// be appended to each particular type of Node. Use `can_append_child` to determine if the
+    /// operation will succeed. A `DoogieError::InvalidChild` error is returned without attempting
+    /// the operation if the child is not allowed, otherwise an error will be returned along with
+    /// the libcmark error code if the operation fails.
+    pub fn append_child(&mut self, child: &mut Node) -> DoogieResult<()> {
+        let pointer = self.checked_pointer()?;
+        if !self.can_append_child(child)? {
+            return Err(DoogieError::InvalidChild {
+                parent: self.get_cmark_type()?,
+                child: child.get_cmark_type()?,
+            });
+        }
+
+        child.unlink();
+        let result: i32;
         unsafe {
-            Ok(CStr::from_ptr(cmark_node_get_title(self.resource.pointer))
-                .to_str()?
-                .to_string())
+            result = cmark_node_append_child(pointer, child.pointer());
         }
-    }
-}
 
-/// Represents an Image element in CommonMark
-pub struct Image {
-    resource: Resource,
-}
+        match result {
+            1 => {
+                ResourceManager::merge(&self.manager(), &child.manager());
+                child.manager().untrack_root(&child.pointer());
+                self.manager().notify(MutationEvent::AppendChild {
+                    parent: self.event_id(),
+                    child: child.event_id(),
+                });
+                Ok(())
+            }
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "append_child",
+            }),
+        }
+    }
 
-impl Image {
-    /// Constructs a new `Image`
-    pub fn new() -> Self {
-        Self {
-            resource: Resource::from_node_type(
-                NodeType::CMarkNodeImage,
-                Rc::new(ResourceManager::new()),
-            ),
+    /// Prepend the given `Node` as the first child of the current `Node` if possible
+    ///
+    /// This follows the same rules as `append_child`. Use `can_prepend_child` to determine if the
+    /// operation will succeed. A `DoogieError::InvalidChild` error is returned if the child is not
+    /// allowed and a `DoogieError::InvalidArgument` error if it is the current `Node` or one of its
+    /// ancestors, in both cases without attempting the operation. Otherwise an error will be
+    /// returned along with the libcmark error code if the operation fails.
+    pub fn prepend_child(&mut self, child: &mut Node) -> DoogieResult<()> {
+        if !self.can_prepend_child(child)? {
+            return Err(DoogieError::InvalidChild {
+                parent: self.get_cmark_type()?,
+                child: child.get_cmark_type()?,
+            });
         }
+
+        self.insert_first_child(child)
     }
-}
 
-/// Iterator over the subtree rooted in the current node.
-///
-/// NodeIterator is a wrapper around the libcmark iterator and so traverses the subtree using the
-/// same scheme documented [here](https://github.com/commonmark/cmark/blob/a5c83d7a426bda38aac838f9815664f6189d3404/src/cmark.h#L151).
-///
-/// # Examples
-///
-/// Transform all Text Nodes to uppercase
-/// ```
-/// use doogie::{parse_document, Node};
-///
-/// let document = "# My Great Document \
-///     \
-///     * Item 1 \
-///     * Item 2 \
-///     * Item 3";
-///
-/// let root = parse_document(document);
-///
-/// for (mut node, _) in root.iter() {
-///     if let Node::Text(ref mut node) = node {
-///         let content = node.get_content().unwrap();
-///         node.set_content(&content.to_uppercase()).unwrap();
-///     }
-/// }
-/// ```
-///
-/// Remove all level 6 Heading Nodes
-/// ```
-/// use doogie::{parse_document, Node};
-///
-/// let document = "# My Great Document \
-///     \
-///     * Item 1 \
-///     * Item 2 \
-///     * Item 3";
-///
-/// let root = parse_document(document);
-///
-/// for (mut node, _) in root.iter() {
-///     let prune = match node {
-///         Node::Heading(ref heading) => heading.get_level() == 6,
-///         _ => false
-///     };
-///
-///     if prune {
-///         node.unlink();
-///     }
-/// }
-/// ```
-pub struct NodeIterator {
-    /// Raw CMark iterator pointer.
-    pointer: *mut CMarkIterPtr,
-}
+    /// Insert the given `Node` as the sibling immediately preceding the current `Node` if possible
+    ///
+    /// The sibling must be a valid child of the parent of the current `Node`, as determined by
+    /// `can_insert_sibling`. A `DoogieError::NoParent` error is returned if the current `Node` has
+    /// no parent, a `DoogieError::InvalidChild` error if the sibling is not allowed and a
+    /// `DoogieError::InvalidArgument` error if the sibling is the current `Node` or one of its
+    /// ancestors, in each case without attempting the operation. Otherwise an error will be
+    /// returned along with the libcmark error code if the operation fails.
+    pub fn insert_before(&mut self, sibling: &mut Node) -> DoogieResult<()> {
+        self.check_sibling(sibling)?;
+        self.insert_sibling_before(sibling)
+    }
 
-impl NodeIterator {
-    /// Construct a new instance.
-    fn new(node_ptr: *mut CMarkNodePtr) -> NodeIterator {
-        let pointer;
+    /// Insert the given `Node` as the sibling immediately following the current `Node` if possible
+    ///
+    /// This follows the same rules as `insert_before`.
+    pub fn insert_after(&mut self, sibling: &mut Node) -> DoogieResult<()> {
+        self.check_sibling(sibling)?;
+        self.check_not_ancestor(sibling)?;
+
+        let pointer = self.checked_pointer()?;
+        let sibling_pointer = sibling.checked_pointer()?;
+        sibling.unlink();
+        let result: i32;
         unsafe {
-            pointer = cmark_iter_new(node_ptr);
+            result = cmark_node_insert_after(pointer, sibling_pointer);
         }
 
-        NodeIterator { pointer }
+        match result {
+            1 => {
+                ResourceManager::merge(&self.manager(), &sibling.manager());
+                sibling.manager().untrack_root(&sibling.pointer());
+                self.manager().notify(MutationEvent::InsertAfter {
+                    node: sibling.event_id(),
+                    after: self.event_id(),
+                });
+                Ok(())
+            }
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "insert_after",
+            }),
+        }
     }
-}
 
-impl Iterator for NodeIterator {
-    type Item = (Node, IterEventType);
+    /// Returns an error unless the given `Node` may be inserted as a sibling of the current `Node`
+    fn check_sibling(&self, sibling: &Node) -> DoogieResult<()> {
+        let parent = match self.parent()? {
+            Some(parent) => parent,
+            None => return Err(DoogieError::NoParent),
+        };
 
-    /// Advance the iterator.
-    fn next(&mut self) -> Option<Self::Item> {
-        let event_type;
-        unsafe {
-            event_type = IterEventType::try_from(cmark_iter_next(self.pointer) as u32);
+        if parent.can_append_child(sibling)? {
+            Ok(())
+        } else {
+            Err(DoogieError::InvalidChild {
+                parent: parent.get_cmark_type()?,
+                child: sibling.get_cmark_type()?,
+            })
         }
+    }
 
-        match event_type {
-            Ok(IterEventType::Done) | Ok(IterEventType::None) => None,
-            Ok(event) => {
-                let node_pointer;
-                unsafe {
-                    node_pointer = cmark_iter_get_node(self.pointer);
-                }
-                match Node::from_raw(node_pointer) {
-                    Ok(node) => Some((node, event)),
-                    Err(_) => {
-                        error!("Could not instantiate Node from Iterator.");
-                        None
-                    }
-                }
+    /// Returns an error if the given `Node` is the current `Node` or one of its ancestors
+    ///
+    /// libcmark only refuses to move a `Node` into itself after the `Node` has been unlinked, so
+    /// this has to be checked before the tree is modified.
+    fn check_not_ancestor(&self, node: &Node) -> DoogieResult<()> {
+        if node.checked_pointer()? == self.checked_pointer()? || self.is_descendant_of(node)? {
+            return Err(DoogieError::InvalidArgument(
+                "a node cannot be moved into itself or into its descendants".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces all of the children of the current `Node` with the given `Node`s, in order
+    ///
+    /// Every new child is validated before the tree is modified, so an error leaves the current
+    /// `Node` untouched. A `DoogieError::InvalidChild` error is returned if a child is not allowed
+    /// by `can_append_child`, a `DoogieError::InvalidArgument` error if a child is the current
+    /// `Node` or one of its ancestors, and a `DoogieError::ResourceUnavailable` error if the
+    /// memory of either has been freed. The previous children are unlinked. A new child may be
+    /// one of the previous children.
+    pub fn set_children(&mut self, children: Vec<Node>) -> DoogieResult<()> {
+        for child in &children {
+            if !self.can_append_child(child)? {
+                return Err(DoogieError::InvalidChild {
+                    parent: self.get_cmark_type()?,
+                    child: child.get_cmark_type()?,
+                });
             }
-            _ => None,
+            self.check_not_ancestor(child)?;
+        }
+
+        for mut child in self.children()? {
+            child.unlink();
+        }
+        for mut child in children {
+            self.append_child(&mut child)?;
         }
+
+        Ok(())
     }
-}
 
-impl Drop for NodeIterator {
-    /// Free the CMark memory allocated for the iterator.
-    fn drop(&mut self) {
-        unsafe {
-            cmark_iter_free(self.pointer);
+    /// Replaces the current `Node` with its children
+    ///
+    /// The children are moved into the parent of the current `Node` at its position, preserving
+    /// their order, after which the now empty `Node` is unlinked. A `DoogieError::NoParent` error
+    /// is returned if the current `Node` has no parent, and a `DoogieError::InvalidChild` error is
+    /// returned without modifying the tree if any of the children are not allowed in the parent.
+    pub fn unwrap(&mut self) -> DoogieResult<()> {
+        let parent = match self.parent()? {
+            Some(parent) => parent,
+            None => return Err(DoogieError::NoParent),
+        };
+
+        let mut children = self.children()?;
+        for child in &children {
+            if !parent.can_append_child(child)? {
+                return Err(DoogieError::InvalidChild {
+                    parent: parent.get_cmark_type()?,
+                    child: child.get_cmark_type()?,
+                });
+            }
+        }
+
+        for child in children.iter_mut() {
+            self.insert_sibling_before(child)?;
         }
+        self.unlink();
+
+        Ok(())
+    }
+
+    /// Wraps the current `Node` in the given wrapper `Node`
+    ///
+    /// The wrapper is inserted at the position of the current `Node`, which is then moved to be
+    /// the last child of the wrapper. A `DoogieError::InvalidChild` error is returned without
+    /// modifying the tree if the wrapper cannot contain the current `Node` or the parent cannot
+    /// contain the wrapper. If the current `Node` has no parent, the wrapper becomes the root of
+    /// its tree.
+    pub fn wrap_in(&mut self, mut wrapper: Node) -> DoogieResult<()> {
+        if !wrapper.can_append_child(self)? {
+            return Err(DoogieError::InvalidChild {
+                parent: wrapper.get_cmark_type()?,
+                child: self.get_cmark_type()?,
+            });
+        }
+
+        if let Some(parent) = self.parent()? {
+            if !parent.can_append_child(&wrapper)? {
+                return Err(DoogieError::InvalidChild {
+                    parent: parent.get_cmark_type()?,
+                    child: wrapper.get_cmark_type()?,
+                });
+            }
+
+            self.insert_sibling_before(&mut wrapper)?;
+        } else {
+            self.manager().track_root(&wrapper.pointer());
+        }
+
+        wrapper.append_child(self)
+    }
+
+    /// Replaces each `Paragraph` whose only child is a `Code` span with a `CodeBlock`
+    ///
+    /// The code block holds the content of the code span. Returns the number of paragraphs that
+    /// were replaced.
+    pub fn promote_code(&mut self) -> DoogieResult<usize> {
+        let mut promoted = Vec::new();
+        for (node, event) in self.iter() {
+            if let (Node::Paragraph(_), IterEventType::Enter) = (&node, event) {
+                let only_child = match (node.first_child()?, node.last_child()?) {
+                    (Some(first), Some(last)) if first.pointer() == last.pointer() => Some(first),
+                    _ => None,
+                };
+
+                if let Some(Node::Code(code)) = only_child {
+                    promoted.push((node, code.get_content()?));
+                }
+            }
+        }
+
+        let count = promoted.len();
+        for (mut paragraph, content) in promoted {
+            let mut block = CodeBlock::new();
+            block.set_content(&format!("{}\n", content))?;
+            paragraph.replace_with(&mut Node::CodeBlock(block))?;
+        }
+
+        Ok(count)
+    }
+
+    /// Merges each `Emph` or `Strong` whose only child is itself an `Emph` or `Strong` with that
+    /// child
+    ///
+    /// Nested emphasis of the same kind collapses into a single `Node` of that kind, so
+    /// `*(*text*)*` becomes `*text*`. Mixed nesting collapses into a single `Strong`, so both
+    /// `**_text_**` and `*__text__*` become `**text**`. Emphasis with several children, or whose
+    /// only child is not emphasis, is left as is. Returns the number of `Node`s removed.
+    pub fn collapse_redundant_emphasis(&mut self) -> DoogieResult<usize> {
+        let mut collapsed = 0;
+        for mut node in self.iter_rev() {
+            let node_type = node.get_cmark_type()?;
+            if node_type != NodeType::CMarkNodeEmph && node_type != NodeType::CMarkNodeStrong {
+                continue;
+            }
+
+            if let (Some(mut first), Some(last)) = (node.first_child()?, node.last_child()?) {
+                if first.pointer() != last.pointer() {
+                    continue;
+                }
+                match (node_type, first.get_cmark_type()?) {
+                    (NodeType::CMarkNodeEmph, NodeType::CMarkNodeStrong) => node.unwrap()?,
+                    (_, NodeType::CMarkNodeEmph) | (_, NodeType::CMarkNodeStrong) => {
+                        first.unwrap()?
+                    }
+                    _ => continue,
+                }
+                collapsed += 1;
+            }
+        }
+
+        Ok(collapsed)
+    }
+
+    /// Replaces the current `SoftBreak` with a `LineBreak`, returning the `LineBreak`
+    ///
+    /// A `DoogieError::WrongNodeType` error is returned if the current `Node` is not a
+    /// `SoftBreak`, and a `DoogieError::NoParent` error if it has no parent. The current `Node`
+    /// is unlinked from the tree.
+    pub fn to_hard_break(&mut self) -> DoogieResult<Node> {
+        self.replace_break(
+            NodeType::CMarkNodeSoftbreak,
+            Node::LineBreak(LineBreak::new()),
+        )
+    }
+
+    /// Replaces the current `LineBreak` with a `SoftBreak`, returning the `SoftBreak`
+    ///
+    /// This is the inverse of `to_hard_break` and fails in the same ways for a `Node` that is not
+    /// a `LineBreak`.
+    pub fn to_soft_break(&mut self) -> DoogieResult<Node> {
+        self.replace_break(
+            NodeType::CMarkNodeLinebreak,
+            Node::SoftBreak(SoftBreak::new()),
+        )
+    }
+
+    /// Replaces the current `Node`, which must be of the `expected` type, with a break `Node`
+    fn replace_break(&mut self, expected: NodeType, mut replacement: Node) -> DoogieResult<Node> {
+        let found = self.get_cmark_type()?;
+        if found != expected {
+            return Err(DoogieError::WrongNodeType { expected, found });
+        }
+        if self.parent()?.is_none() {
+            return Err(DoogieError::NoParent);
+        }
+
+        self.replace_with(&mut replacement)?;
+        Node::from_raw(replacement.pointer(), self.manager())
+    }
+
+    /// Replaces the current `Node` with the given `Node`, unlinking the current `Node`
+    ///
+    /// The caller is responsible for ensuring that the current `Node` has a parent that can
+    /// contain the replacement.
+    fn replace_with(&mut self, replacement: &mut Node) -> DoogieResult<()> {
+        self.insert_sibling_before(replacement)?;
+        self.unlink();
+
+        Ok(())
+    }
+
+    /// Inserts the given `Node` as the first child of the current `Node`
+    ///
+    /// The caller is responsible for ensuring that the current `Node` can contain the child.
+    fn insert_first_child(&mut self, child: &mut Node) -> DoogieResult<()> {
+        self.check_not_ancestor(child)?;
+        let pointer = self.checked_pointer()?;
+        let child_pointer = child.checked_pointer()?;
+        child.unlink();
+        let result: i32;
+        unsafe {
+            result = cmark_node_prepend_child(pointer, child_pointer);
+        }
+
+        match result {
+            1 => {
+                ResourceManager::merge(&self.manager(), &child.manager());
+                child.manager().untrack_root(&child.pointer());
+                self.manager().notify(MutationEvent::PrependChild {
+                    parent: self.event_id(),
+                    child: child.event_id(),
+                });
+                Ok(())
+            }
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "prepend_child",
+            }),
+        }
+    }
+
+    /// Returns the children of the current `Node` in document order
+    fn children(&self) -> DoogieResult<Vec<Node>> {
+        let mut children = Vec::new();
+        let mut next = self.first_child()?;
+        while let Some(child) = next {
+            next = child.next_sibling()?;
+            children.push(child);
+        }
+
+        Ok(children)
+    }
+
+    /// Inserts the given `Node` as the sibling immediately preceding the current `Node`
+    ///
+    /// The caller is responsible for ensuring that the parent can contain the sibling.
+    fn insert_sibling_before(&mut self, sibling: &mut Node) -> DoogieResult<()> {
+        self.check_not_ancestor(sibling)?;
+        let pointer = self.checked_pointer()?;
+        let sibling_pointer = sibling.checked_pointer()?;
+        sibling.unlink();
+        let result: i32;
+        unsafe {
+            result = cmark_node_insert_before(pointer, sibling_pointer);
+        }
+
+        match result {
+            1 => {
+                ResourceManager::merge(&self.manager(), &sibling.manager());
+                sibling.manager().untrack_root(&sibling.pointer());
+                self.manager().notify(MutationEvent::InsertBefore {
+                    node: sibling.event_id(),
+                    before: self.event_id(),
+                });
+                Ok(())
+            }
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "insert_before",
+            }),
+        }
+    }
+
+    /// Moves all of the children of `other` to the end of the current `Node`, preserving their
+    /// order
+    ///
+    /// `other` is left empty. The moved `Node`s become part of the tree of the current `Node`, and
+    /// the memory of both documents is managed together from then on, as described for `close`. A
+    /// `DoogieError::InvalidChild` error is returned without modifying either tree if any of the
+    /// children are not allowed in the current `Node`.
+    pub fn append_document(&mut self, other: &mut Node) -> DoogieResult<()> {
+        let mut children = other.children()?;
+        for child in &children {
+            if !self.can_append_child(child)? {
+                return Err(DoogieError::InvalidChild {
+                    parent: self.get_cmark_type()?,
+                    child: child.get_cmark_type()?,
+                });
+            }
+        }
+
+        for child in children.iter_mut() {
+            self.append_child(child)?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends a `Paragraph` containing the given text as the last child of the current `Node`
+    pub fn append_paragraph_text(&mut self, text: &str) -> DoogieResult<()> {
+        let mut paragraph = Node::Paragraph(Paragraph::new());
+        paragraph.append_child(&mut Node::new_text(text)?)?;
+        self.append_child(&mut paragraph)
+    }
+
+    /// Appends a `Heading` of the given level containing the given text as the last child of the
+    /// current `Node`
+    pub fn append_heading(&mut self, level: u32, text: &str) -> DoogieResult<()> {
+        let mut heading = Heading::new();
+        heading.set_level(level as usize)?;
+        let mut heading = Node::Heading(heading);
+        heading.append_child(&mut Node::new_text(text)?)?;
+        self.append_child(&mut heading)
+    }
+
+    /// Appends a fenced `CodeBlock` with the given language and code as the last child of the
+    /// current `Node`
+    pub fn append_code_block(&mut self, lang: &str, code: &str) -> DoogieResult<()> {
+        let mut code_block = CodeBlock::new();
+        code_block.set_fence_info(&lang.to_string())?;
+        code_block.set_content(&code.to_string())?;
+        self.append_child(&mut Node::CodeBlock(code_block))
+    }
+
+    /// Constructs a new `Text` `Node` with the given content
+    fn new_text(content: &str) -> DoogieResult<Node> {
+        let mut text = Text::new();
+        text.set_content(&content.to_string())?;
+        Ok(Node::Text(text))
+    }
+
+    /// Determines if the given `Node` is a potentially valid child of the current `Node`
+    pub fn can_append_child(&self, child: &Node) -> DoogieResult<bool> {
+        let child_type = child.get_cmark_type()?;
+
+        let result = match self {
+            Node::Document(_) => DOCUMENT_CHILDREN.contains(&child_type),
+            Node::BlockQuote(_) => BLOCK_QUOTE_CHILDREN.contains(&child_type),
+            Node::List(_) => child_type == NodeType::CMarkNodeItem,
+            Node::Item(_) => ITEM_CHILDREN.contains(&child_type),
+            Node::CodeBlock(_) => CODE_BLOCK_CHILDREN.contains(&child_type),
+            Node::HtmlBlock(_) => HTML_BLOCK_CHILDREN.contains(&child_type),
+            Node::CustomBlock(_) => CUSTOM_BLOCK_CHILDREN.contains(&child_type),
+            Node::Paragraph(_) => PARAGRAPH_CHILDREN.contains(&child_type),
+            Node::Heading(_) => HEADING_CHILDREN.contains(&child_type),
+            Node::ThematicBreak(_) => THEMATIC_BREAK_CHILDREN.contains(&child_type),
+            Node::Text(_) => TEXT_CHILDREN.contains(&child_type),
+            Node::SoftBreak(_) => SOFT_BREAK_CHILDREN.contains(&child_type),
+            Node::LineBreak(_) => LINE_BREAK_CHILDREN.contains(&child_type),
+            Node::Code(_) => CODE_CHILDREN.contains(&child_type),
+            Node::HtmlInline(_) => INLINE_HTML_CHILDREN.contains(&child_type),
+            Node::CustomInline(_) => CUSTOM_INLINE_CHILDREN.contains(&child_type),
+            Node::Emph(_) => EMPH_CHILDREN.contains(&child_type),
+            Node::Strong(_) => STRONG_CHILDREN.contains(&child_type),
+            Node::Link(_) => LINK_CHILDREN.contains(&child_type),
+            Node::Image(_) => IMAGE_CHILDREN.contains(&child_type),
+        };
+
+        Ok(result)
+    }
+
+    /// Determines if the given `Node` is a potentially valid first child of the current `Node`
+    ///
+    /// The position of a child does not matter to CommonMark, so this applies the same rules as
+    /// `can_append_child`.
+    pub fn can_prepend_child(&self, child: &Node) -> DoogieResult<bool> {
+        self.can_append_child(child)
+    }
+
+    /// Determines if the given `Node` is a potentially valid sibling of the current `Node`
+    ///
+    /// A sibling is a child of the parent of the current `Node`, so it is checked against the
+    /// rules of the parent rather than those of the current `Node`. A `Node` without a parent
+    /// cannot have siblings.
+    pub fn can_insert_sibling(&self, sibling: &Node) -> DoogieResult<bool> {
+        match self.parent()? {
+            Some(parent) => parent.can_append_child(sibling),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the types of the `Node`s that the current `Node` may contain
+    ///
+    /// These are the same rules that `can_append_child` applies.
+    pub fn allowed_child_types(&self) -> &'static [NodeType] {
+        match self {
+            Node::Document(_) | Node::BlockQuote(_) | Node::Item(_) => DOCUMENT_CHILD_TYPES,
+            Node::List(_) => LIST_CHILD_TYPES,
+            Node::CustomBlock(_) => CUSTOM_BLOCK_CHILD_TYPES,
+            Node::Paragraph(_)
+            | Node::Heading(_)
+            | Node::CustomInline(_)
+            | Node::Emph(_)
+            | Node::Strong(_)
+            | Node::Link(_)
+            | Node::Image(_) => PARAGRAPH_CHILD_TYPES,
+            Node::CodeBlock(_)
+            | Node::HtmlBlock(_)
+            | Node::ThematicBreak(_)
+            | Node::Text(_)
+            | Node::SoftBreak(_)
+            | Node::LineBreak(_)
+            | Node::Code(_)
+            | Node::HtmlInline(_) => &[],
+        }
+    }
+
+    /// Renders the document AST rooted at the current `Node` into textual CommonMark form
+    ///
+    /// Like the other render methods, this renders a `Node` whose resources have been freed as an
+    /// empty string.
+    pub fn render_commonmark(&self) -> String {
+        String::from_utf8_lossy(&self.render_commonmark_bytes()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark without the trailing
+    /// newline
+    ///
+    /// Only the single `\n` that libcmark appends after the last block is removed. Any other
+    /// trailing whitespace, such as that of a hard line break or of the last line of a code block
+    /// followed by an empty line, is kept.
+    pub fn render_commonmark_trimmed(&self) -> String {
+        let mut rendered = self.render_commonmark();
+        if rendered.ends_with('\n') {
+            rendered.pop();
+        }
+
+        rendered
+    }
+
+    /// Renders the children of the current `Node` into CommonMark without the markup of the
+    /// current `Node` itself
+    ///
+    /// Block children are rendered on their own and separated by a blank line so that the result
+    /// parses back into the same blocks. Inline children are copied into a temporary `Paragraph`
+    /// and rendered together, so that soft and hard line breaks between them are kept, and the
+    /// trailing newline of the paragraph is dropped.
+    pub fn render_inner_commonmark(&self) -> DoogieResult<String> {
+        let mut rendered = String::new();
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph)?;
+        paragraph.manager().track_root(&paragraph.pointer());
+        for child in self.children()? {
+            if child.is_block() {
+                if !rendered.is_empty() {
+                    rendered.push('\n');
+                }
+                rendered.push_str(&child.render_commonmark());
+            } else {
+                paragraph.append_child(&mut child.deep_copy()?)?;
+            }
+        }
+        if paragraph.has_children() {
+            rendered.push_str(paragraph.render_commonmark().trim_right_matches('\n'));
+        }
+
+        Ok(rendered)
+    }
+
+    /// Returns whether the current `Node` and the given `Node` render to the same CommonMark
+    ///
+    /// This ignores the identity and source positions of the `Node`s, as well as any structural
+    /// differences that CommonMark cannot express, such as adjacent `Text` Nodes.
+    pub fn renders_same(&self, other: &Node) -> bool {
+        self.render_commonmark() == other.render_commonmark()
+    }
+
+    /// Returns a fingerprint of the structure and content of the subtree rooted at the current
+    /// `Node`
+    ///
+    /// The hash covers the type, attributes and literal content of every `Node` in document
+    /// order, as serialized by `render_xml_bytes`, and ignores their identity and source
+    /// positions. Structurally identical trees therefore have the same hash. The serialization is
+    /// hashed with 64-bit FNV-1a, so the value does not change between runs or platforms, only
+    /// with the XML output of libcmark. A `DoogieError::ResourceUnavailable` error is returned if
+    /// the memory of the document has been freed.
+    pub fn content_hash(&self) -> DoogieResult<u64> {
+        let xml = self.render_bytes(cmark_render_xml, DoogieOptions::default())?;
+        Ok(fnv1a(&xml))
+    }
+
+    /// Renders the document AST rooted at the current `Node` into canonical CommonMark
+    ///
+    /// libcmark's renderer already discards the blank lines, bullet characters and heading styles
+    /// of the source, but its output may itself parse into a slightly different tree, for example
+    /// when adjacent lists are separated. The output is therefore parsed and rendered a second
+    /// time, so that rendering the normalized text again reproduces it exactly.
+    pub fn render_commonmark_normalized(&self) -> String {
+        parse_document(&self.render_commonmark()).render_commonmark()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark with setext headings
+    ///
+    /// libcmark always renders ATX headings, so the rendered output is parsed again and the lines
+    /// of its level 1 and level 2 headings are rewritten with `===` and `---` underlines
+    /// respectively. Setext headings do not exist for the other levels, which keep their ATX form,
+    /// as do headings nested within other blocks. A heading also keeps its ATX form if its rendered
+    /// text would not parse as a paragraph on its own line, e.g. because it would start a list or
+    /// a block quote. Lines of code blocks and HTML blocks that merely look like headings are left
+    /// untouched.
+    pub fn render_commonmark_setext(&self) -> String {
+        let rendered = self.render_commonmark();
+        let mut underlines = Vec::new();
+        for child in parse_document(&rendered).children().unwrap_or_default() {
+            if let Node::Heading(ref heading) = child {
+                match heading.get_level() {
+                    1 => underlines.push((child.get_start_line(), '=')),
+                    2 => underlines.push((child.get_start_line(), '-')),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut output = String::with_capacity(rendered.len());
+        for (index, line) in rendered.split_terminator('\n').enumerate() {
+            let underline = underlines
+                .iter()
+                .find(|&&(start_line, _)| start_line as usize == index + 1)
+                .map(|&(_, underline)| underline);
+
+            if let Some(underline) = underline {
+                let title = line.trim_left_matches('#').trim();
+                if is_paragraph_line(title) {
+                    output.push_str(title);
+                    output.push('\n');
+                    for _ in 0..std::cmp::max(3, title.chars().count()) {
+                        output.push(underline);
+                    }
+                    output.push('\n');
+                    continue;
+                }
+            }
+
+            output.push_str(line);
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark, keeping the source
+    /// of each `CodeBlock` exactly as it was parsed
+    ///
+    /// The rendered output is parsed again to find the lines of each rendered code block, which
+    /// are replaced with the lines of the corresponding block in the source retained by
+    /// `parse_document`, including its fences or indentation. The prefixes of the enclosing block
+    /// quotes and list items are taken from the rendered output. Code blocks without a source
+    /// position, or whose content has changed since they were parsed, are rendered normally, as are
+    /// code blocks that were moved in from another document. A `DoogieError::SourceUnavailable`
+    /// error is returned if the current `Node` was not parsed from the retained source.
+    pub fn render_commonmark_preserve_code(&self) -> DoogieResult<String> {
+        let manager = self.manager();
+        if manager.source_of(self.pointer()).is_none() {
+            return Err(DoogieError::SourceUnavailable);
+        }
+
+        let rendered = self.render_commonmark();
+        let reparsed = parse_document(&rendered);
+        let originals = self.code_block_nodes();
+        let copies = reparsed.code_block_nodes();
+        if originals.len() != copies.len() {
+            return Ok(rendered);
+        }
+
+        let lines: Vec<&str> = rendered.split_terminator('\n').collect();
+        let mut output = String::with_capacity(rendered.len());
+        let mut next = 0;
+        for (original, copy) in originals.iter().zip(&copies) {
+            let source = match manager.source_of(original.pointer()) {
+                Some(source) => source,
+                None => continue,
+            };
+            let source_lines = match code_block_source(source, original)? {
+                Some(source_lines) => source_lines,
+                None => continue,
+            };
+
+            let first = copy.get_start_line() as usize - 1;
+            let mut last = copy.get_end_line() as usize - 1;
+            while last > first && is_blank_line(lines[last]) {
+                last -= 1;
+            }
+            let prefix = lines[first]
+                .get(..copy.get_start_column() as usize - 1)
+                .unwrap_or("");
+            let continuation: String = prefix
+                .chars()
+                .map(|c| if c == '>' { c } else { ' ' })
+                .collect();
+
+            for line in &lines[next..first] {
+                output.push_str(line);
+                output.push('\n');
+            }
+            for (index, line) in source_lines.iter().enumerate() {
+                let prefix = if index == 0 { prefix } else { &continuation };
+                if line.is_empty() {
+                    output.push_str(prefix.trim_right());
+                } else {
+                    output.push_str(prefix);
+                    output.push_str(line);
+                }
+                output.push('\n');
+            }
+            next = last + 1;
+        }
+        for line in &lines[next..] {
+            output.push_str(line);
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+
+    /// Returns the `CodeBlock`s of the subtree in document order
+    fn code_block_nodes(&self) -> Vec<Node> {
+        self.iter()
+            .filter(|&(ref node, ref event)| match (node, event) {
+                (Node::CodeBlock(_), IterEventType::Enter) => true,
+                _ => false,
+            })
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark with the given
+    /// marker for all bullet list items
+    ///
+    /// libcmark does not allow choosing the bullet marker, so the rendered output is parsed again
+    /// and the marker of each bullet list item is replaced at the position reported for the item.
+    /// Nested lists and lists inside block quotes are handled, while emphasis, code and ordered
+    /// list markers are left untouched. A `DoogieError::InvalidArgument` error is returned if the
+    /// marker is not one of `-`, `+` and `*`.
+    pub fn normalize_bullet_marker(&self, marker: char) -> DoogieResult<String> {
+        if marker != '-' && marker != '+' && marker != '*' {
+            return Err(DoogieError::InvalidArgument(format!(
+                "{:?} is not a bullet list marker",
+                marker
+            )));
+        }
+
+        let rendered = self.render_commonmark();
+        let reparsed = parse_document(&rendered);
+        let mut offsets = Vec::new();
+        for (node, event) in reparsed.iter() {
+            if event != IterEventType::Enter || node.get_cmark_type()? != NodeType::CMarkNodeItem {
+                continue;
+            }
+            if let Some(Node::List(list)) = node.parent()? {
+                if list.get_list_type()? == ListType::CMarkBulletList {
+                    offsets.extend(source_offset(
+                        &rendered,
+                        node.get_start_line(),
+                        node.get_start_column(),
+                    ));
+                }
+            }
+        }
+
+        let mut bytes = rendered.into_bytes();
+        for offset in offsets {
+            if bytes[offset] == b'-' || bytes[offset] == b'+' || bytes[offset] == b'*' {
+                bytes[offset] = marker as u8;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark as raw bytes
+    ///
+    /// The bytes are copied exactly as libcmark produced them, without any UTF-8 validation.
+    pub fn render_commonmark_bytes(&self) -> Vec<u8> {
+        self.render_bytes(cmark_render_commonmark, DoogieOptions::default())
+            .unwrap_or_default()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into CommonMark with the given
+    /// libcmark options
+    pub fn render_commonmark_with_options(&self, options: DoogieOptions) -> String {
+        let rendered = self.render_bytes(cmark_render_commonmark, options);
+        String::from_utf8_lossy(&rendered.unwrap_or_default()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into textual xml form
+    pub fn render_xml(&self) -> String {
+        String::from_utf8_lossy(&self.render_xml_bytes()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into xml as raw bytes
+    ///
+    /// The bytes are copied exactly as libcmark produced them, without any UTF-8 validation.
+    pub fn render_xml_bytes(&self) -> Vec<u8> {
+        self.render_bytes(cmark_render_xml, DoogieOptions::default())
+            .unwrap_or_default()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into xml with the given libcmark
+    /// options
+    pub fn render_xml_with_options(&self, options: DoogieOptions) -> String {
+        let rendered = self.render_bytes(cmark_render_xml, options);
+        String::from_utf8_lossy(&rendered.unwrap_or_default()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into HTML
+    ///
+    /// Any `Node` may be rendered, not just a `Document`, so that a single subtree can be turned
+    /// into an HTML fragment. Like the other render methods, this renders a `Node` whose resources
+    /// have been freed as an empty string, as well as a `Node` that libcmark failed to render.
+    pub fn render_html(&self) -> String {
+        String::from_utf8_lossy(&self.render_html_bytes()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into HTML as raw bytes
+    ///
+    /// The bytes are copied exactly as libcmark produced them, without any UTF-8 validation.
+    pub fn render_html_bytes(&self) -> Vec<u8> {
+        self.render_bytes(cmark_render_html, DoogieOptions::default())
+            .unwrap_or_default()
+    }
+
+    /// Renders the document AST rooted at the current `Node` into HTML with the given libcmark
+    /// options
+    pub fn render_html_with_options(&self, options: DoogieOptions) -> String {
+        let rendered = self.render_bytes(cmark_render_html, options);
+        String::from_utf8_lossy(&rendered.unwrap_or_default()).into_owned()
+    }
+
+    /// Renders the document AST rooted at the current `Node` with the given libcmark renderer
+    fn render_bytes(
+        &self,
+        renderer: unsafe extern "C" fn(*mut CMarkNodePtr, c_int) -> *const c_char,
+        options: DoogieOptions,
+    ) -> DoogieResult<Vec<u8>> {
+        let pointer = self.checked_pointer()?;
+        let rendered;
+        unsafe {
+            rendered = renderer(pointer, options.bits() as c_int);
+        }
+
+        if rendered.is_null() {
+            warn!("Should not have gotten a null pointer for rendered text.");
+            Err(DoogieError::NodeNone)
+        } else {
+            unsafe { Ok(take_rendered(rendered)) }
+        }
+    }
+
+    /// Returns an iterator over the `Node`s of the document subtree rooted at the current `Node`
+    pub fn iter(&self) -> NodeIterator {
+        NodeIterator::new(self.pointer(), self.manager())
+    }
+
+    /// Returns an iterator over the `Node`s of the document subtree rooted at the current `Node`
+    /// along with their parents
+    ///
+    /// The parents are tracked with a stack as the iteration enters and exits containers, rather
+    /// than looked up for each `Node`. The parent reported for the current `Node` is its parent in
+    /// the document, if any.
+    pub fn iter_with_parent(&self) -> impl Iterator<Item = (Node, Option<Node>, IterEventType)> {
+        let mut parents: Vec<Option<Node>> = vec![self.parent().unwrap_or(None)];
+
+        self.iter().map(move |(node, event)| {
+            if event == IterEventType::Exit {
+                parents.pop();
+            }
+            let parent = match parents.last() {
+                Some(&Some(ref parent)) => parent.itself().ok(),
+                _ => None,
+            };
+            let container = node
+                .get_cmark_type()
+                .map(|node_type| node_type.is_container())
+                .unwrap_or(false);
+            if event == IterEventType::Enter && container {
+                parents.push(node.itself().ok());
+            }
+
+            (node, parent, event)
+        })
+    }
+
+    /// Returns an iterator over the `Node`s of the subtree rooted at the current `Node` in reverse
+    /// pre-order
+    ///
+    /// Each `Node` is yielded once, so the deepest last descendant comes first and the current
+    /// `Node` comes last. Since libcmark can only iterate forwards, the `Node`s are collected
+    /// before iteration begins, which takes O(n) memory in the size of the subtree.
+    pub fn iter_rev(&self) -> impl Iterator<Item = Node> {
+        let nodes: Vec<Node> = self
+            .iter()
+            .filter(|&(_, ref event)| *event == IterEventType::Enter)
+            .map(|(node, _)| node)
+            .collect();
+
+        nodes.into_iter().rev()
+    }
+
+    /// Returns the start line from the original CMark document corresponding to the current `Node`
+    pub fn get_start_line(&self) -> u32 {
+        match self.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_start_line(pointer) as u32 },
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the start column from the original CMark document corresponding to this `Node
+    pub fn get_start_column(&self) -> u32 {
+        match self.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_start_column(pointer) as u32 },
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the end line from the original CMark document corresponding to the current `Node`
+    pub fn get_end_line(&self) -> u32 {
+        match self.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_end_line(pointer) as u32 },
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the end column from the original CMark document corresponding to the current `Node`
+    pub fn get_end_column(&self) -> u32 {
+        match self.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_end_column(pointer) as u32 },
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the byte offset into the parsed source at which the current `Node` starts
+    ///
+    /// The offset is computed from the source retained by `parse_document`, so `None` is returned
+    /// for `Node`s that were constructed rather than parsed, as well as for `Node`s that were
+    /// moved in from another document, whose positions refer to a different source.
+    pub fn get_start_byte(&self) -> Option<usize> {
+        let manager = self.manager();
+        let source = manager.source_of(self.pointer())?;
+        let start = source_offset(source, self.get_start_line(), self.get_start_column())?;
+
+        if source.is_char_boundary(start) {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the byte offset into the parsed source just past the end of the current `Node`
+    ///
+    /// Like `get_start_byte`, `None` is returned if the source is not available.
+    pub fn get_end_byte(&self) -> Option<usize> {
+        let manager = self.manager();
+        let source = manager.source_of(self.pointer())?;
+        let end = source_offset(source, self.get_end_line(), self.get_end_column())?;
+        let (_, line_end) = line_bounds(source, self.get_end_line())?;
+        let end = std::cmp::min(end + 1, line_end);
+
+        if source.is_char_boundary(end) {
+            Some(end)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the original source text of the current `Node`
+    ///
+    /// The text is sliced from the source retained by `parse_document` using the start and end
+    /// positions of the `Node`. `None` is returned if the source is not available, e.g. for
+    /// `Node`s that were constructed rather than parsed or that were moved in from another
+    /// document.
+    pub fn source_text(&self) -> DoogieResult<Option<String>> {
+        let manager = self.manager();
+        match (
+            manager.source_of(self.pointer()),
+            self.get_start_byte(),
+            self.get_end_byte(),
+        ) {
+            (Some(source), Some(start), Some(end)) if start <= end => {
+                Ok(Some(source[start..end].to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of the given bytes
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    hash
+}
+
+/// Returns the anchor slug for the given heading text
+fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the reference labels found in a run of text
+///
+/// Each character is paired with a flag which is set if the character was escaped in the source
+/// and so cannot open or close a label.
+fn bracketed_labels(run: &[(char, bool)]) -> Vec<String> {
+    let bracket = |index: usize, expected: char| match run.get(index) {
+        Some(&(c, false)) => c == expected,
+        _ => false,
+    };
+    let label_end = |start: usize| {
+        (start + 1..run.len()).find(|&index| bracket(index, ']') || bracket(index, '['))
+    };
+    let label = |start: usize, end: usize| {
+        let text: String = run[start + 1..end].iter().map(|&(c, _)| c).collect();
+        text.split_whitespace().collect::<Vec<&str>>().join(" ")
+    };
+
+    let mut labels = Vec::new();
+    let mut index = 0;
+    while index < run.len() {
+        if !bracket(index, '[') {
+            index += 1;
+            continue;
+        }
+
+        let end = match label_end(index) {
+            Some(end) if bracket(end, ']') => end,
+            Some(end) => {
+                index = end;
+                continue;
+            }
+            None => break,
+        };
+        let text = label(index, end);
+
+        if bracket(end + 1, '[') {
+            if let Some(ref_end) = label_end(end + 1).filter(|&ref_end| bracket(ref_end, ']')) {
+                let reference = label(end + 1, ref_end);
+                let reference = if reference.is_empty() {
+                    text
+                } else {
+                    reference
+                };
+                if !reference.is_empty() {
+                    labels.push(reference);
+                }
+                index = ref_end + 1;
+                continue;
+            }
+        }
+
+        let inline_link = match run.get(end + 1) {
+            Some(&(c, _)) => c == '(',
+            None => false,
+        };
+        let subscript = index > 0 && run[index - 1].0.is_alphanumeric();
+        if !inline_link && !subscript && !text.is_empty() {
+            labels.push(text);
+        }
+        index = end + 1;
+    }
+
+    labels
+}
+
+/// Resolves a relative URL against a base URL
+///
+/// Returns `None` if the URL should be left as it is, i.e. if it is empty, has a scheme, is
+/// protocol relative or only contains a fragment. Dot segments in the resolved path are removed.
+fn rebase_url(base: &str, url: &str) -> Option<String> {
+    let has_scheme = url.find(':').map_or(false, |colon| {
+        let scheme = &url[..colon];
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+    });
+    if url.is_empty() || has_scheme || url.starts_with("//") || url.starts_with('#') {
+        return None;
+    }
+
+    let path_start = match base.find("://") {
+        Some(separator) => base[separator + 3..]
+            .find('/')
+            .map_or(base.len(), |slash| separator + 3 + slash),
+        None => 0,
+    };
+    let (origin, base_path) = base.split_at(path_start);
+
+    let joined = if url.starts_with('/') {
+        url.to_string()
+    } else {
+        let directory = base_path
+            .rfind('/')
+            .map_or("", |slash| &base_path[..slash + 1]);
+        match (origin.is_empty(), directory.is_empty()) {
+            (false, true) => format!("/{}", url),
+            _ => format!("{}{}", directory, url),
+        }
+    };
+
+    let suffix_start = joined
+        .find(|c| c == '?' || c == '#')
+        .unwrap_or(joined.len());
+    let (path, suffix) = joined.split_at(suffix_start);
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." => {
+                if last {
+                    segments.push("");
+                }
+            }
+            ".." => {
+                match segments.last() {
+                    Some(&"") if segments.len() == 1 => (),
+                    Some(&"..") | None => segments.push(".."),
+                    Some(_) => {
+                        segments.pop();
+                    }
+                }
+                if last {
+                    segments.push("");
+                }
+            }
+            _ => segments.push(part),
+        }
+    }
+
+    Some(format!("{}{}{}", origin, segments.join("/"), suffix))
+}
+
+/// Copies the bytes of a string rendered by libcmark and frees it
+///
+/// The pointer must not be null.
+unsafe fn take_rendered(rendered: *const c_char) -> Vec<u8> {
+    let bytes = CStr::from_ptr(rendered).to_bytes().to_vec();
+    libc::free(rendered as *mut c_void);
+    bytes
+}
+
+/// Returns the text represented by the body of an HTML character reference, if it is valid
+///
+/// A reference to the null character represents U+FFFD as in HTML.
+fn decode_entity(reference: &str) -> Option<String> {
+    let code_point = if reference.starts_with("#x") || reference.starts_with("#X") {
+        u32::from_str_radix(&reference[2..], 16).ok()
+    } else if reference.starts_with('#') {
+        reference[1..].parse::<u32>().ok()
+    } else {
+        return HTML_ENTITIES
+            .iter()
+            .find(|&&(name, _)| name == reference)
+            .map(|&(_, value)| value.to_string());
+    };
+
+    match code_point {
+        Some(0) => Some('\u{fffd}'.to_string()),
+        Some(code_point) => std::char::from_u32(code_point).map(|c| c.to_string()),
+        None => None,
+    }
+}
+
+/// Returns the byte offsets of the start and end of the given 1-based line of the source
+///
+/// The end offset excludes the line terminator.
+fn line_bounds(source: &str, line: u32) -> Option<(usize, usize)> {
+    if line == 0 {
+        return None;
+    }
+
+    let start = match line {
+        1 => 0,
+        _ => source.match_indices('\n').nth(line as usize - 2)?.0 + 1,
+    };
+    let end = source[start..]
+        .find('\n')
+        .map(|offset| start + offset)
+        .unwrap_or(source.len());
+
+    Some((start, end))
+}
+
+/// Returns whether the line before the given 1-based line of the source is blank
+///
+/// See `is_blank_line` for which lines are blank.
+fn follows_blank_line(source: &str, line: u32) -> bool {
+    match line_bounds(source, line.saturating_sub(1)) {
+        Some((start, end)) => is_blank_line(&source[start..end]),
+        None => false,
+    }
+}
+
+/// Returns whether a line is blank
+///
+/// The markers of enclosing block quotes are ignored, so a line holding only `>` is blank.
+fn is_blank_line(line: &str) -> bool {
+    line.trim_matches(|c: char| c.is_whitespace() || c == '>')
+        .is_empty()
+}
+
+/// Returns whether the given line parses as a single paragraph on its own
+fn is_paragraph_line(line: &str) -> bool {
+    let root = parse_document(line);
+    match (root.first_child(), root.last_child()) {
+        (Ok(Some(first @ Node::Paragraph(_))), Ok(Some(last))) => first == last,
+        _ => false,
+    }
+}
+
+/// Returns the lines of the source spanned by a `CodeBlock`, without the prefixes of the blocks
+/// that contain it
+///
+/// The lines include the fences of a fenced code block but not the blank lines that follow an
+/// indented one. `None` is returned if the `CodeBlock` has no source position or if its content
+/// no longer matches the source.
+fn code_block_source<'a>(source: &'a str, block: &Node) -> DoogieResult<Option<Vec<&'a str>>> {
+    let content = match *block {
+        Node::CodeBlock(ref code_block) => code_block.get_content()?,
+        _ => return Ok(None),
+    };
+    let (start, end) = (block.get_start_line(), block.get_end_line());
+    let width = block.get_start_column() as usize;
+    if start == 0 || width == 0 || end < start {
+        return Ok(None);
+    }
+
+    let mut lines = Vec::new();
+    for line in start..end + 1 {
+        let (line_start, line_end) = match line_bounds(source, line) {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        let text = source[line_start..line_end].trim_right_matches('\r');
+        lines.push(text.get(width - 1..).unwrap_or(""));
+    }
+    while lines.len() > 1 && lines[lines.len() - 1].trim().is_empty() {
+        lines.pop();
+    }
+
+    let fence = match lines[0].trim_left().chars().next() {
+        Some(c)
+            if lines[0]
+                .trim_left()
+                .starts_with(&format!("{}{}{}", c, c, c)) =>
+        {
+            Some(c)
+        }
+        _ => None,
+    };
+    let inner = match fence {
+        Some(c) if lines.len() > 1 && lines[lines.len() - 1].trim_left().starts_with(c) => {
+            &lines[1..lines.len() - 1]
+        }
+        Some(_) => &lines[1..],
+        None => &lines[..],
+    };
+    let content_lines: Vec<&str> = content.split_terminator('\n').collect();
+    let matches = inner.len() == content_lines.len()
+        && inner
+            .iter()
+            .zip(&content_lines)
+            .all(|(line, content_line)| line.ends_with(content_line));
+
+    if matches {
+        Ok(Some(lines))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns the byte ranges of the bare `http://` and `https://` URLs in a text
+///
+/// A URL must not directly follow a letter or digit and must have something after its scheme. It
+/// ends at whitespace or `<`, leaving out trailing punctuation that most likely belongs to the
+/// surrounding sentence, including closing parentheses without a matching opening one.
+fn find_urls(text: &str) -> Vec<(usize, usize)> {
+    let mut urls = Vec::new();
+    let mut offset = 0;
+    while offset < text.len() {
+        let start = match ["http://", "https://"]
+            .iter()
+            .filter_map(|scheme| text[offset..].find(scheme))
+            .min()
+        {
+            Some(index) => offset + index,
+            None => break,
+        };
+        let scheme_len = if text[start..].starts_with("https://") {
+            8
+        } else {
+            7
+        };
+
+        let mut end = text[start..]
+            .find(|c: char| c.is_whitespace() || c == '<')
+            .map_or(text.len(), |index| start + index);
+        loop {
+            let url = &text[start..end];
+            match url.chars().next_back() {
+                Some(c) if ".,:;!?'\"*_~".contains(c) => end -= c.len_utf8(),
+                Some(')') if url.matches('(').count() < url.matches(')').count() => end -= 1,
+                _ => break,
+            }
+        }
+
+        let follows_word = text[..start]
+            .chars()
+            .next_back()
+            .map_or(false, |c| c.is_alphanumeric());
+        if !follows_word && end > start + scheme_len {
+            urls.push((start, end));
+        }
+        offset = ::std::cmp::max(end, start + scheme_len);
+    }
+
+    urls
+}
+
+/// Returns whether the descendants of a `Node` hold nothing but whitespace
+///
+/// Whitespace may be held in `Text`s and breaks, possibly within paragraphs and emphasis. Any
+/// other descendant makes the content meaningful.
+fn has_only_whitespace(node: &Node) -> DoogieResult<bool> {
+    for (descendant, _) in node.iter().skip(1) {
+        let empty = match descendant {
+            Node::Text(ref text) => text.get_content()?.trim().is_empty(),
+            Node::Paragraph(_)
+            | Node::Emph(_)
+            | Node::Strong(_)
+            | Node::SoftBreak(_)
+            | Node::LineBreak(_) => true,
+            _ => false,
+        };
+
+        if !empty {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Returns the byte offset into the source of a libcmark line and column position
+///
+/// libcmark positions are 1-based and columns are counted in bytes.
+fn source_offset(source: &str, line: u32, column: u32) -> Option<usize> {
+    let (start, end) = line_bounds(source, line)?;
+    if column == 0 || start + column as usize - 1 > end {
+        return None;
+    }
+
+    Some(start + column as usize - 1)
+}
+
+/// Converts a libcmark return code into a `DoogieResult`
+fn check_return_code(result: c_int, op: &'static str) -> DoogieResult<()> {
+    match result {
+        1 => Ok(()),
+        i => Err(DoogieError::ReturnCode { code: i as u32, op }),
+    }
+}
+
+/// Appends the text contributed by a single `NodeIterator` step to the buffer
+///
+/// This is the building block of `Node::inner_text`.
+fn push_inner_text(buffer: &mut String, node: &Node, event: &IterEventType) -> DoogieResult<()> {
+    match (node, event) {
+        (Node::Text(text), IterEventType::Enter) => buffer.push_str(&text.get_content()?),
+        (Node::Code(code), IterEventType::Enter) => buffer.push_str(&code.get_content()?),
+        (Node::SoftBreak(_), IterEventType::Enter) => buffer.push(' '),
+        (Node::LineBreak(_), IterEventType::Enter) => buffer.push('\n'),
+        (Node::Document(_), IterEventType::Exit)
+        | (Node::BlockQuote(_), IterEventType::Exit)
+        | (Node::List(_), IterEventType::Exit)
+        | (Node::Item(_), IterEventType::Exit)
+        | (Node::CustomBlock(_), IterEventType::Exit)
+        | (Node::Paragraph(_), IterEventType::Exit)
+        | (Node::Heading(_), IterEventType::Exit) => {
+            if !buffer.is_empty() && !buffer.ends_with('\n') {
+                buffer.push('\n');
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Represents the root `Node` of a document in the CommonMark AST
+pub struct Document {
+    resource: Resource,
+}
+
+impl Document {
+    /// Returns the source text that the `Document` was parsed from, if it was parsed
+    pub fn get_source(&self) -> Option<&str> {
+        self.resource.manager.source_of(self.resource.pointer)
+    }
+
+    /// Returns the byte offset into the source of the given 1-based line and column
+    ///
+    /// Unlike the positions reported by libcmark, `column` is counted in characters rather than
+    /// bytes. A column one past the last character of a line maps to the end of that line. `None`
+    /// is returned if the `Document` has no source or the position lies outside of it.
+    pub fn byte_offset(&self, line: u32, column: u32) -> Option<usize> {
+        let source = self.get_source()?;
+        let (start, end) = line_bounds(source, line)?;
+        if column == 0 {
+            return None;
+        }
+
+        let target = column as usize - 1;
+        let mut chars = source[start..end].char_indices();
+        match chars.nth(target) {
+            Some((offset, _)) => Some(start + offset),
+            None if source[start..end].chars().count() == target => Some(end),
+            None => None,
+        }
+    }
+
+    /// Constructs a new `Document`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeDocument,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Wraps the root pointer of a document obtained directly from libcmark
+    ///
+    /// Ownership of the tree is taken by the returned `Document`, which frees it once it and
+    /// every `Node` of the tree have been dropped, as with `parse_document`. A
+    /// `DoogieError::NodeNone` error is returned if the pointer is null and a
+    /// `DoogieError::WrongNodeType` error is returned if it does not point to a document, in
+    /// which case ownership is not taken.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to a valid libcmark node that is the root of its tree and is not
+    /// owned or freed by any other code.
+    pub unsafe fn from_raw(pointer: *mut CMarkNodePtr) -> DoogieResult<Self> {
+        if pointer.is_null() {
+            return Err(DoogieError::NodeNone);
+        }
+
+        let found = NodeType::try_from(cmark_node_get_type(pointer) as u32)?;
+        if found != NodeType::CMarkNodeDocument {
+            return Err(DoogieError::WrongNodeType {
+                expected: NodeType::CMarkNodeDocument,
+                found,
+            });
+        }
+
+        let manager = Rc::new(ResourceManager::new());
+        manager.track_root(&pointer);
+        Ok(Document {
+            resource: Resource { pointer, manager },
+        })
+    }
+
+    /// Consolidates all adjacent `Text` `Node`s in the document into single `Text` `Node`s.
+    pub fn consolidate_text_nodes(&mut self) {
+        if let Ok(pointer) = self.resource.checked_pointer() {
+            unsafe {
+                cmark_consolidate_text_nodes(pointer);
+            }
+        }
+    }
+}
+
+/// Represents a Block Quote element in CommonMark
+pub struct BlockQuote {
+    resource: Resource,
+}
+
+impl BlockQuote {
+    /// Constructs a new `BlockQuote`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeBlockQuote,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a List element in CommonMark
+///
+/// Lists are meta-containers in that they are classified as container blocks in CommonMark, but can
+/// only contain `Item` elements as children.
+pub struct List {
+    resource: Resource,
+}
+
+impl List {
+    /// Constructs a new `List`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeList,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Constructs a new bullet `List` with an `Item` holding a paragraph of text for each entry
+    pub fn from_items<S: AsRef<str>>(items: &[S]) -> DoogieResult<Self> {
+        let mut list = Node::List(List::new());
+        for item in items {
+            let mut item_node = Node::Item(Item::new());
+            item_node.append_paragraph_text(item.as_ref())?;
+            list.append_child(&mut item_node)?;
+        }
+
+        match list {
+            Node::List(list) => Ok(list),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Constructs a new ordered `List` starting at 1 with an `Item` holding a paragraph of text
+    /// for each entry
+    pub fn from_ordered_items<S: AsRef<str>>(items: &[S]) -> DoogieResult<Self> {
+        let mut list = List::from_items(items)?;
+        list.set_list_type(ListType::CMarkOrderedList)?;
+        list.set_list_start(1)?;
+        Ok(list)
+    }
+
+    /// Constructs a new ordered `List` starting at `start`
+    ///
+    /// A tight list renders without blank lines between its items.
+    pub fn ordered(start: i32, tight: bool) -> DoogieResult<Self> {
+        let mut list = List::new();
+        list.set_list_type(ListType::CMarkOrderedList)?;
+        list.set_list_start(start)?;
+        list.set_list_tight(tight)?;
+        Ok(list)
+    }
+
+    /// Constructs a new bullet `List`
+    ///
+    /// A tight list renders without blank lines between its items.
+    pub fn bullet(tight: bool) -> DoogieResult<Self> {
+        let mut list = List::new();
+        list.set_list_type(ListType::CMarkBulletList)?;
+        list.set_list_tight(tight)?;
+        Ok(list)
+    }
+
+    /// Returns an enum representing the type of list i.e. Bullet or Ordered
+    pub fn get_list_type(&self) -> DoogieResult<ListType> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe { ListType::try_from(cmark_node_get_list_type(pointer) as u32) }
+    }
+
+    /// Sets the type of list i.e. Bullet or Ordered
+    pub fn set_list_type(&mut self, list_type: ListType) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_list_type(pointer, u32::from(list_type) as c_int);
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_list_type",
+            }),
+        }
+    }
+
+    /// Returns the starting number in the case of ordered lists.
+    pub fn get_list_start(&self) -> i32 {
+        match self.resource.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_list_start(pointer) as i32 },
+            Err(_) => 0,
+        }
+    }
+
+    /// Sets the starting number used in the case of ordered lists.
+    pub fn set_list_start(&mut self, start: i32) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_list_start(pointer, start as c_int);
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_list_start",
+            }),
+        }
+    }
+
+    /// Returns whether the list is tight, i.e. its items are not separated by blank lines.
+    pub fn get_list_tight(&self) -> bool {
+        match self.resource.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_list_tight(pointer) != 0 },
+            Err(_) => false,
+        }
+    }
+
+    /// Sets whether the list is tight, i.e. its items are not separated by blank lines.
+    pub fn set_list_tight(&mut self, tight: bool) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_list_tight(pointer, tight as c_int);
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_list_tight",
+            }),
+        }
+    }
+
+    /// Returns the delimiter type used in the case of ordered lists.
+    pub fn get_delim_type(&self) -> DoogieResult<DelimType> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe { DelimType::try_from(cmark_node_get_list_delim(pointer) as u32) }
+    }
+
+    /// Returns the `Item`s of the current List in order
+    pub fn items(&self) -> DoogieResult<Vec<Node>> {
+        let pointer = self.resource.checked_pointer()?;
+        Node::from_raw(pointer, self.resource.manager.clone())?.children()
+    }
+
+    /// Returns the `Item`s that make the current List loose, in order
+    ///
+    /// Like libcmark, an `Item` is considered to introduce looseness if a blank line separates it
+    /// from the next `Item` or separates two of its own children. Blank lines do not survive in
+    /// the AST, so they are found in the source retained by `parse_document` and a
+    /// `DoogieError::SourceUnavailable` error is returned for Lists that were not parsed, or that
+    /// contain `Node`s moved in from another document.
+    pub fn loose_items(&self) -> DoogieResult<Vec<Node>> {
+        let manager = &self.resource.manager;
+        let source = match manager.source_of(self.resource.pointer) {
+            Some(source) => source,
+            None => return Err(DoogieError::SourceUnavailable),
+        };
+
+        let mut loose = Vec::new();
+        for item in self.items()? {
+            let mut following = item.children()?.into_iter().skip(1).collect::<Vec<_>>();
+            following.extend(item.next_sibling()?);
+
+            let mut blank = false;
+            for node in &following {
+                if manager.source_of(node.pointer()).is_none() {
+                    return Err(DoogieError::SourceUnavailable);
+                }
+                blank = blank || follows_blank_line(source, node.get_start_line());
+            }
+            if blank {
+                loose.push(item);
+            }
+        }
+
+        Ok(loose)
+    }
+
+    /// Returns the number of `Item`s that make the current List loose
+    ///
+    /// See `loose_items` for how they are determined.
+    pub fn loose_item_count(&self) -> DoogieResult<usize> {
+        Ok(self.loose_items()?.len())
+    }
+}
+
+/// Represents a List Item in CommonMark
+pub struct Item {
+    resource: Resource,
+}
+
+impl Item {
+    /// Constructs a new `Item`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeItem,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Determines if the current Item has no meaningful content
+    ///
+    /// An Item is empty if it has no children or if its only content is whitespace, possibly
+    /// within paragraphs, emphasis and line breaks. Any other content, such as code, a link or a
+    /// nested list, makes it non-empty.
+    pub fn is_empty(&self) -> DoogieResult<bool> {
+        let pointer = self.resource.checked_pointer()?;
+        let item = Node::from_raw(pointer, self.resource.manager.clone())?;
+        has_only_whitespace(&item)
+    }
+}
+
+/// Represents a Code Block in CommonMark
+pub struct CodeBlock {
+    resource: Resource,
+}
+
+impl CodeBlock {
+    /// Constructs a new `CodeBlock`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeCodeBlock,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the info text in the case of a Fenced Code Block
+    pub fn get_fence_info(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe {
+            Ok(CStr::from_ptr(cmark_node_get_fence_info(pointer))
+                .to_str()?
+                .to_string())
+        }
+    }
+
+    /// Sets the info text for the code block
+    pub fn set_fence_info(&mut self, info: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let info = CString::new(info.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_fence_info(pointer, info.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1),
+            err => Err(DoogieError::ReturnCode {
+                code: err as u32,
+                op: "set_fence_info",
+            }),
+        }
+    }
+
+    /// Returns the textual content of the current Code Block element
+    pub fn get_content(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            return Ok(String::new());
+        } else {
+            unsafe {
+                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+            }
+        }
+    }
+
+    /// Returns the textual content of the current Code Block element, replacing invalid UTF-8 with
+    /// U+FFFD REPLACEMENT CHARACTER
+    ///
+    /// Unlike `get_content`, this never fails.
+    pub fn get_content_lossy(&self) -> String {
+        let pointer = match self.resource.checked_pointer() {
+            Ok(pointer) => pointer,
+            Err(_) => return String::new(),
+        };
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(result).to_string_lossy().into_owned() }
+        }
+    }
+
+    /// Sets the textual content of the current Code Block element
+    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let content = CString::new(content.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_literal(pointer, content.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_content",
+            }),
+        }
+    }
+
+    /// Determines if the current Code Block is fenced rather than indented
+    ///
+    /// The linked libcmark does not record how a code block was written, so this is determined
+    /// from the retained source. A fenced block starts with a run of at least three backticks or
+    /// tildes at its start column. An indented block can only start that way if its content does,
+    /// in which case its first content line is the rest of the opening line and every line it
+    /// spans after its content is blank, whereas a closed fence ends with a non-blank closing
+    /// line. The one ambiguity left is an unclosed fence that repeats its opening line as its
+    /// first content line and ends with a blank line, which is reported as indented. Code Blocks
+    /// that were not parsed from the retained source, such as those constructed with
+    /// `CodeBlock::new`, were written neither way and are not considered fenced.
+    pub fn is_fenced(&self) -> bool {
+        let line = match self.first_source_line() {
+            Some(line) => line,
+            None => return false,
+        };
+        let marker = match line.chars().next() {
+            Some(marker) if marker == '`' || marker == '~' => marker,
+            _ => return false,
+        };
+        if line.chars().take_while(|c| *c == marker).count() < 3 {
+            return false;
+        }
+
+        let content = self.get_content_lossy();
+        if content.lines().next() != Some(line.trim_right_matches('\r')) {
+            return true;
+        }
+
+        !self.spans_blank_lines_after(content.lines().count() as u32)
+    }
+
+    /// Returns whether every source line spanned by the current Code Block after the first `skip`
+    /// lines is blank from its start column onwards
+    fn spans_blank_lines_after(&self, skip: u32) -> bool {
+        let source = match self.resource.manager.source_of(self.resource.pointer) {
+            Some(source) => source,
+            None => return false,
+        };
+        let (start, end, column);
+        unsafe {
+            start = cmark_node_get_start_line(self.resource.pointer) as u32;
+            end = cmark_node_get_end_line(self.resource.pointer) as u32;
+            column = cmark_node_get_start_column(self.resource.pointer) as usize;
+        }
+
+        (start + skip..end + 1).all(|line| match line_bounds(source, line) {
+            Some((line_start, line_end)) => source[line_start..line_end]
+                .get(column.saturating_sub(1)..)
+                .map_or(true, |rest| rest.trim().is_empty()),
+            None => false,
+        })
+    }
+
+    /// Returns the fence character, fence length and fence offset of a fenced Code Block
+    ///
+    /// Like `is_fenced`, the details are recovered from the retained source, so `None` is returned
+    /// for indented code blocks and for code blocks without source. The offset is the indentation
+    /// of the opening fence, measured from the start of its line or from the single space that
+    /// follows a block quote or list marker preceding it on the same line.
+    pub fn fence_details(&self) -> DoogieResult<Option<(char, usize, usize)>> {
+        if !self.is_fenced() {
+            return Ok(None);
+        }
+
+        let line = match self.first_source_line() {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let fence_char = match line.chars().next() {
+            Some(fence_char) => fence_char,
+            None => return Ok(None),
+        };
+        let fence_length = line.chars().take_while(|c| *c == fence_char).count();
+
+        let source = self
+            .resource
+            .manager
+            .source_of(self.resource.pointer)
+            .ok_or(DoogieError::SourceUnavailable)?;
+        let fence_start = self.start_offset().ok_or(DoogieError::SourceUnavailable)?;
+        let before = source[..fence_start].rsplit('\n').next().unwrap_or("");
+        let indent = before.len() - before.trim_right_matches(' ').len();
+        let fence_offset = match before.trim_right_matches(' ').is_empty() {
+            true => indent,
+            false => indent.saturating_sub(1),
+        };
+
+        Ok(Some((fence_char, fence_length, fence_offset)))
+    }
+
+    /// Returns the source text of the line on which the current Code Block starts
+    ///
+    /// The text begins at the start column of the Code Block and excludes the line terminator.
+    fn first_source_line(&self) -> Option<&str> {
+        let source = self.resource.manager.source_of(self.resource.pointer)?;
+        let start = self.start_offset()?;
+        let rest = source.get(start..)?;
+
+        Some(rest.split('\n').next().unwrap_or(rest))
+    }
+
+    /// Returns the byte offset into the retained source at which the current Code Block starts
+    fn start_offset(&self) -> Option<usize> {
+        let source = self.resource.manager.source_of(self.resource.pointer)?;
+        let pointer = self.resource.checked_pointer().ok()?;
+        let line;
+        let column;
+        unsafe {
+            line = cmark_node_get_start_line(pointer) as u32;
+            column = cmark_node_get_start_column(pointer) as u32;
+        }
+
+        source_offset(source, line, column)
+    }
+}
+
+/// Represents a block of HTML in CommonMark
+pub struct HtmlBlock {
+    resource: Resource,
+}
+
+impl HtmlBlock {
+    /// Constructs a new `HtmlBlock`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeHtmlBlock,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents an ambiguous Block Element
+pub struct CustomBlock {
+    resource: Resource,
+}
+
+impl CustomBlock {
+    /// Constructs a new `CustomBlock`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeCustomBlock,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Paragraph element in CommonMark
+pub struct Paragraph {
+    resource: Resource,
+}
+
+impl Paragraph {
+    /// Constructs a new `Paragraph`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeParagraph,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Heading element in CommonMark
+pub struct Heading {
+    resource: Resource,
+}
+
+impl Heading {
+    /// Constructs a new `Heading`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeHeading,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the heading level of the current Heading
+    pub fn get_level(&self) -> usize {
+        match self.resource.checked_pointer() {
+            Ok(pointer) => unsafe { cmark_node_get_heading_level(pointer) as usize },
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns whether the current Heading was written as an ATX or a setext heading
+    ///
+    /// The style is recovered from the source position of a Heading parsed by `parse_document`.
+    /// An ATX heading always fits on a single line, while a setext heading ends on the line of its
+    /// underline, below its text. A `DoogieError::SourceUnavailable` error is returned for
+    /// Headings that were not parsed.
+    pub fn style(&self) -> DoogieResult<HeadingStyle> {
+        let pointer = self.resource.checked_pointer()?;
+        if self.resource.manager.source_of(pointer).is_none() {
+            return Err(DoogieError::SourceUnavailable);
+        }
+        let (start, end);
+        unsafe {
+            start = cmark_node_get_start_line(pointer);
+            end = cmark_node_get_end_line(pointer);
+        }
+
+        if start == 0 {
+            Err(DoogieError::SourceUnavailable)
+        } else if end > start {
+            Ok(HeadingStyle::Setext)
+        } else {
+            Ok(HeadingStyle::Atx)
+        }
+    }
+
+    /// Sets the heading level of the current Heading
+    ///
+    /// libcmark only accepts levels from 1 to 6.
+    pub fn set_level(&mut self, level: usize) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_heading_level(pointer, level as c_int);
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_level",
+            }),
+        }
+    }
+}
+
+/// Represents a Thematic Break element in CommonMark
+pub struct ThematicBreak {
+    resource: Resource,
+}
+
+impl ThematicBreak {
+    /// Constructs a new `ThematicBreak`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeThematicBreak,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Text element in CommonMark
+pub struct Text {
+    resource: Resource,
+}
+
+impl Text {
+    /// Constructs a new `Text`
+    pub fn new() -> Self {
+        Text {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeText,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the textual content of the current Text element
+    pub fn get_content(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            return Ok(String::new());
+        } else {
+            unsafe {
+                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+            }
+        }
+    }
+
+    /// Returns the textual content of the current Text element, replacing invalid UTF-8 with
+    /// U+FFFD REPLACEMENT CHARACTER
+    ///
+    /// Unlike `get_content`, this never fails.
+    pub fn get_content_lossy(&self) -> String {
+        let pointer = match self.resource.checked_pointer() {
+            Ok(pointer) => pointer,
+            Err(_) => return String::new(),
+        };
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(result).to_string_lossy().into_owned() }
+        }
+    }
+
+    /// Sets the textual content of the current Text element
+    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let content = CString::new(content.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_literal(pointer, content.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_content",
+            }),
+        }
+    }
+}
+
+/// Represents a Soft Break element in CommonMark
+pub struct SoftBreak {
+    resource: Resource,
+}
+
+impl SoftBreak {
+    /// Constructs a new `SoftBreak`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeSoftbreak,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Line Break element in CommonMark
+pub struct LineBreak {
+    resource: Resource,
+}
+
+impl LineBreak {
+    /// Constructs a new `LineBreak`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeLinebreak,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents an inline Code element in CommonMark
+pub struct Code {
+    resource: Resource,
+}
+
+impl Code {
+    /// Constructs a new `Code`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeCode,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the textual content of the current Text element
+    pub fn get_content(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            return Ok(String::new());
+        } else {
+            unsafe {
+                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+            }
+        }
+    }
+
+    /// Returns the textual content of the current Code element, replacing invalid UTF-8 with
+    /// U+FFFD REPLACEMENT CHARACTER
+    ///
+    /// Unlike `get_content`, this never fails.
+    pub fn get_content_lossy(&self) -> String {
+        let pointer = match self.resource.checked_pointer() {
+            Ok(pointer) => pointer,
+            Err(_) => return String::new(),
+        };
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(result).to_string_lossy().into_owned() }
+        }
+    }
+
+    /// Sets the textual content of the current Text element
+    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let content = CString::new(content.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_literal(pointer, content.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_content",
+            }),
+        }
+    }
+}
+
+/// Represents an inline HTML element in CommonMark
+pub struct HtmlInline {
+    resource: Resource,
+}
+
+impl HtmlInline {
+    /// Constructs a new `HtmlInline`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeHtmlInline,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the textual content of the current Inline HTML element
+    pub fn get_content(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        let result;
+        unsafe {
+            result = cmark_node_get_literal(pointer);
+        }
+
+        if result.is_null() {
+            return Ok(String::new());
+        } else {
+            unsafe {
+                return Ok(CStr::from_ptr(result).to_str()?.to_string());
+            }
+        }
+    }
+
+    /// Sets the textual content of the current Inline HTML element
+    pub fn set_content(&mut self, content: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let content = CString::new(content.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_literal(pointer, content.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_content",
+            }),
+        }
+    }
+}
+
+/// Represents an ambiguous inline element
+pub struct CustomInline {
+    resource: Resource,
+}
+
+impl CustomInline {
+    /// Constructs a new `CustomInline`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeCustomInline,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represenets an Emph element in CommonMark
+pub struct Emph {
+    resource: Resource,
+}
+
+impl Emph {
+    /// Constructs a new `Emph`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeEmph,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Strong element in CommonMark
+pub struct Strong {
+    resource: Resource,
+}
+
+impl Strong {
+    /// Constructs a new `Strong`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeStrong,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+}
+
+/// Represents a Link element in CommonMark
+pub struct Link {
+    resource: Resource,
+}
+
+impl Link {
+    /// Constructs a new `Link`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeLink,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the URL portion of the Link
+    pub fn get_url(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe {
+            Ok(CStr::from_ptr(cmark_node_get_url(pointer))
+                .to_str()?
+                .to_string())
+        }
+    }
+
+    /// Sets the URL portion of the Link
+    pub fn set_url(&mut self, url: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let url = CString::new(url.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_url(pointer, url.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_url",
+            }),
+        }
+    }
+
+    /// Returns the title portion of the Link
+    pub fn get_title(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe {
+            Ok(CStr::from_ptr(cmark_node_get_title(pointer))
+                .to_str()?
+                .to_string())
+        }
+    }
+}
+
+/// Represents an Image element in CommonMark
+pub struct Image {
+    resource: Resource,
+}
+
+impl Image {
+    /// Constructs a new `Image`
+    pub fn new() -> Self {
+        Self {
+            resource: Resource::from_node_type(
+                NodeType::CMarkNodeImage,
+                Rc::new(ResourceManager::new()),
+            ),
+        }
+    }
+
+    /// Returns the URL portion of the Image
+    pub fn get_url(&self) -> DoogieResult<String> {
+        let pointer = self.resource.checked_pointer()?;
+        unsafe {
+            Ok(CStr::from_ptr(cmark_node_get_url(pointer))
+                .to_str()?
+                .to_string())
+        }
+    }
+
+    /// Sets the URL portion of the Image
+    pub fn set_url(&mut self, url: &String) -> DoogieResult<u32> {
+        let pointer = self.resource.checked_pointer()?;
+        let url = CString::new(url.as_bytes())?;
+        let result: i32;
+        unsafe {
+            result = cmark_node_set_url(pointer, url.as_ptr());
+        }
+
+        match result {
+            1 => Ok(1 as u32),
+            i => Err(DoogieError::ReturnCode {
+                code: i as u32,
+                op: "set_url",
+            }),
+        }
+    }
+}
+
+/// Iterator over the subtree rooted in the current node.
+///
+/// NodeIterator is a wrapper around the libcmark iterator and so traverses the subtree using the
+/// same scheme documented [here](https://github.com/commonmark/cmark/blob/a5c83d7a426bda38aac838f9815664f6189d3404/src/cmark.h#L151).
+///
+/// # Examples
+///
+/// Transform all Text Nodes to uppercase
+/// ```
+/// use doogie::parse_document;
+///
+/// let document = "# My Great Document \
+///     \
+///     * Item 1 \
+///     * Item 2 \
+///     * Item 3";
+///
+/// let mut root = parse_document(document);
+///
+/// root.map_text(|content| content.to_uppercase()).unwrap();
+/// ```
+///
+/// Remove all level 6 Heading Nodes
+/// ```
+/// use doogie::{parse_document, Node};
+///
+/// let document = "# My Great Document \
+///     \
+///     * Item 1 \
+///     * Item 2 \
+///     * Item 3";
+///
+/// let mut root = parse_document(document);
+///
+/// root.retain(|node| match *node {
+///     Node::Heading(ref heading) => heading.get_level() != 6,
+///     _ => true
+/// }).unwrap();
+/// ```
+pub struct NodeIterator {
+    /// Raw CMark iterator pointer.
+    pointer: *mut CMarkIterPtr,
+    /// Manager of the tree being iterated.
+    manager: Rc<ResourceManager>,
+}
+
+impl NodeIterator {
+    /// Construct a new instance.
+    fn new(node_ptr: *mut CMarkNodePtr, manager: Rc<ResourceManager>) -> NodeIterator {
+        let pointer;
+        unsafe {
+            pointer = cmark_iter_new(node_ptr);
+        }
+
+        NodeIterator { pointer, manager }
+    }
+}
+
+impl Iterator for NodeIterator {
+    type Item = (Node, IterEventType);
+
+    /// Advance the iterator.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.manager.is_freed() {
+            return None;
+        }
+
+        let event_type;
+        unsafe {
+            event_type = IterEventType::try_from(cmark_iter_next(self.pointer) as u32);
+        }
+
+        match event_type {
+            Ok(IterEventType::Done) | Ok(IterEventType::None) => None,
+            Ok(event) => {
+                let node_pointer;
+                unsafe {
+                    node_pointer = cmark_iter_get_node(self.pointer);
+                }
+                match Node::from_raw(node_pointer, self.manager.clone()) {
+                    Ok(node) => Some((node, event)),
+                    Err(_) => {
+                        error!("Could not instantiate Node from Iterator.");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for NodeIterator {
+    /// Free the CMark memory allocated for the iterator.
+    fn drop(&mut self) {
+        unsafe {
+            cmark_iter_free(self.pointer);
+        }
+    }
+}
+
+/// Owning iterator over the `Node`s of a document subtree
+///
+/// Created by `Node::into_iter`. The root `Node` of the subtree is held for as long as the
+/// iterator is alive, so the iteration can continue even though the caller gave up the root. The
+/// libcmark iterator is declared first so that it is freed before the root is released.
+pub struct NodeIntoIterator {
+    /// Iterator over the subtree.
+    iter: NodeIterator,
+    /// Root of the subtree, kept alive for the duration of the iteration.
+    _root: Node,
+}
+
+impl Iterator for NodeIntoIterator {
+    type Item = (Node, IterEventType);
+
+    /// Advance the iterator.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl IntoIterator for Node {
+    type Item = (Node, IterEventType);
+    type IntoIter = NodeIntoIterator;
+
+    /// Consumes the `Node` and returns an iterator over the `Node`s of its subtree
+    fn into_iter(self) -> NodeIntoIterator {
+        NodeIntoIterator {
+            iter: self.iter(),
+            _root: self,
+        }
+    }
+}
+
+/// Iterator over the siblings on one side of a `Node`
+struct SiblingIterator {
+    /// Raw CMark pointer of the most recently visited node.
+    pointer: *mut CMarkNodePtr,
+    /// Manager of the tree being iterated.
+    manager: Rc<ResourceManager>,
+    /// Walk towards the next sibling if true, otherwise towards the previous sibling.
+    forward: bool,
+}
+
+impl Iterator for SiblingIterator {
+    type Item = Node;
+
+    /// Advance the iterator.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pointer.is_null() || self.manager.is_freed() {
+            return None;
+        }
+
+        unsafe {
+            self.pointer = match self.forward {
+                true => cmark_node_next(self.pointer),
+                false => cmark_node_previous(self.pointer),
+            };
+        }
+
+        if self.pointer.is_null() {
+            return None;
+        }
+
+        match Node::from_raw(self.pointer, self.manager.clone()) {
+            Ok(node) => Some(node),
+            Err(_) => {
+                error!("Could not instantiate Node from SiblingIterator.");
+                self.pointer = std::ptr::null_mut();
+                None
+            }
+        }
+    }
+}
+
+/// Source of the ids that tag the nodes of each parsed document
+static NEXT_SOURCE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Manages the memory resources of `Node` instances.
+///
+/// All of the `Node`s of a tree share the same manager, which also holds the source text of
+/// parsed documents and the mutation observer of the tree. Once a `Node` is moved into a tree
+/// of another manager, the memory of both is managed together: the roots are handed over to the
+/// manager of the destination tree and the other manager forwards to it from then on.
+struct ResourceManager {
+    roots: RefCell<Vec<(*mut CMarkNodePtr, bool)>>,
+    source: Option<String>,
+    source_id: usize,
+    freed: Cell<bool>,
+    owned: bool,
+    observer: RefCell<Option<Box<FnMut(MutationEvent)>>>,
+    merged_into: RefCell<Option<Rc<ResourceManager>>>,
+}
+
+impl Debug for ResourceManager {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.debug_struct("ResourceManager")
+            .field("roots", &self.roots)
+            .field("source", &self.source)
+            .field("source_id", &self.source_id)
+            .field("freed", &self.freed)
+            .field("owned", &self.owned)
+            .field("observer", &self.observer.borrow().is_some())
+            .field("merged", &self.merged_into.borrow().is_some())
+            .finish()
+    }
+}
+
+impl Drop for ResourceManager {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl ResourceManager {
+    /// Construct a new ResourceManager instance.
+    pub fn new() -> ResourceManager {
+        ResourceManager {
+            roots: RefCell::new(Vec::new()),
+            source: None,
+            source_id: 0,
+            freed: Cell::new(false),
+            owned: true,
+            observer: RefCell::new(None),
+            merged_into: RefCell::new(None),
+        }
+    }
+
+    /// Construct a new ResourceManager instance for memory that is owned elsewhere.
+    ///
+    /// The roots tracked by the manager are never freed by it.
+    pub fn borrowed() -> ResourceManager {
+        ResourceManager {
+            roots: RefCell::new(Vec::new()),
+            source: None,
+            source_id: 0,
+            freed: Cell::new(false),
+            owned: false,
+            observer: RefCell::new(None),
+            merged_into: RefCell::new(None),
+        }
+    }
+
+    /// Construct a new ResourceManager instance that retains the source text of a document.
+    pub fn with_source(source: String) -> ResourceManager {
+        ResourceManager {
+            roots: RefCell::new(Vec::new()),
+            source: Some(source),
+            source_id: NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed),
+            freed: Cell::new(false),
+            owned: true,
+            observer: RefCell::new(None),
+            merged_into: RefCell::new(None),
+        }
+    }
+
+    /// Tracks the given pointer as a root Node of some tree or subtree
+    pub fn track_root(&self, pointer: &*mut CMarkNodePtr) {
+        let owned = self.owned;
+        let target = self.target();
+        let mut roots = target
+            .as_ref()
+            .map_or(self, |target| &**target)
+            .roots
+            .borrow_mut();
+        if !roots.iter().any(|&(root, _)| root == *pointer) {
+            roots.push((pointer.clone(), owned));
+        }
+    }
+
+    /// Returns the manager that the memory of this manager has been merged into, if any
+    fn target(&self) -> Option<Rc<ResourceManager>> {
+        let mut target = self.merged_into.borrow().clone()?;
+        loop {
+            let next = target.merged_into.borrow().clone();
+            match next {
+                Some(next) => target = next,
+                None => return Some(target),
+            }
+        }
+    }
+
+    /// Merges the memory managed by `other` into the memory managed by `manager`
+    ///
+    /// This is needed when a `Node` is moved between trees of different managers. The `Node`s of
+    /// both trees then keep the merged memory alive, and freeing it through either manager makes
+    /// it unavailable to all of them, so that no `Node` is left pointing into freed memory.
+    pub fn merge(manager: &Rc<ResourceManager>, other: &Rc<ResourceManager>) {
+        let target = manager.target().unwrap_or_else(|| manager.clone());
+        let source = other.target().unwrap_or_else(|| other.clone());
+        if Rc::ptr_eq(&target, &source) {
+            return;
+        }
+
+        let roots: Vec<_> = source.roots.borrow_mut().drain(..).collect();
+        target.roots.borrow_mut().extend(roots);
+        *source.merged_into.borrow_mut() = Some(target);
+    }
+
+    /// Tracks the root of a tree parsed from the retained source
+    ///
+    /// Every node of the tree is tagged with the id of the source through its libcmark user data,
+    /// so that `source_of` can tell the parsed nodes apart from nodes moved in from other trees.
+    pub fn track_parsed_root(&self, root: &*mut CMarkNodePtr) {
+        unsafe {
+            let iter = cmark_iter_new(*root);
+            while let Ok(event) = IterEventType::try_from(cmark_iter_next(iter) as u32) {
+                match event {
+                    IterEventType::Enter => {
+                        cmark_node_set_user_data(
+                            cmark_iter_get_node(iter),
+                            self.source_id as *mut c_void,
+                        );
+                    }
+                    IterEventType::Exit => (),
+                    _ => break,
+                }
+            }
+            cmark_iter_free(iter);
+        }
+        self.track_root(root);
+    }
+
+    /// Returns the retained source text if the given node was parsed from it
+    ///
+    /// The source positions of nodes that were constructed, or parsed from the source of another
+    /// manager and moved into this tree, do not refer to the retained source, so `None` is
+    /// returned for them, as well as once the managed memory has been freed.
+    pub fn source_of(&self, pointer: *mut CMarkNodePtr) -> Option<&str> {
+        let source = self.source.as_ref()?;
+        if self.is_freed() {
+            return None;
+        }
+
+        let id;
+        unsafe {
+            id = cmark_node_get_user_data(pointer) as usize;
+        }
+        if id == self.source_id {
+            Some(source)
+        } else {
+            None
+        }
+    }
+
+    /// Frees every tracked root immediately and marks the managed memory as unavailable
+    ///
+    /// Roots are only released from tracking, not freed, if the memory is not owned.
+    pub fn free(&self) {
+        match self.target() {
+            Some(target) => target.release(),
+            None => self.release(),
+        }
+    }
+
+    /// Frees the roots tracked directly by this manager and marks them as unavailable
+    fn release(&self) {
+        let mut roots = self.roots.borrow_mut();
+        for (pointer, owned) in roots.drain(..) {
+            if owned {
+                unsafe {
+                    cmark_node_free(pointer);
+                }
+            }
+        }
+        self.freed.set(true);
+    }
+
+    /// Notifies the mutation observer, if any, of a structural change
+    ///
+    /// The observer is taken out while it runs so that it may itself use the tree.
+    pub fn notify(&self, event: MutationEvent) {
+        let observer = self.observer.borrow_mut().take();
+        if let Some(mut observer) = observer {
+            observer(event);
+
+            let mut slot = self.observer.borrow_mut();
+            if slot.is_none() {
+                *slot = Some(observer);
+            }
+        }
+    }
+
+    /// Returns the number of roots being tracked
+    pub fn root_count(&self) -> usize {
+        match self.target() {
+            Some(target) => target.root_count(),
+            None => self.roots.borrow().len(),
+        }
+    }
+
+    /// Determines if the managed memory has been freed
+    pub fn is_freed(&self) -> bool {
+        match self.target() {
+            Some(target) => target.is_freed(),
+            None => self.freed.get(),
+        }
+    }
+
+    /// Removes the tracking for a given pointer
+    pub fn untrack_root(&self, pointer: &*mut CMarkNodePtr) {
+        let target = self.target();
+        let mut roots = target
+            .as_ref()
+            .map_or(self, |target| &**target)
+            .roots
+            .borrow_mut();
+        roots.retain(|&(root, _)| root != *pointer);
+    }
+
+    #[cfg(test)]
+    /// Determines if the given pointer is currently being tracked
+    pub fn is_tracking(&self, pointer: &*mut CMarkNodePtr) -> bool {
+        let target = self.target();
+        let roots = target
+            .as_ref()
+            .map_or(self, |target| &**target)
+            .roots
+            .borrow();
+        roots.iter().any(|&(root, _)| root == *pointer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        c_char, cmark_node_append_child, cmark_node_first_child, cmark_node_free,
+        cmark_node_get_type, cmark_node_new, cmark_node_set_literal, cmark_parse_document,
+        decode_entities, diff, escape_commonmark, fnv1a, parse_document,
+        parse_document_with_frontmatter, parse_document_with_options, parse_xml, CMarkNodePtr,
+        Code, CodeBlock, DocStats, Document, Heading, HeadingStyle, Item, IterEventType, List,
+        MutationEvent, Node, NodeResource, NodeType, OutlineNode, Paragraph, Parser,
+        ResourceManager, Text, TreeEdit,
+    };
+    use constants::*;
+    use errors::DoogieError;
+    use proptest::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Returns some arbitrary alphanumeric textual content
+    fn arb_content(max_words: usize) -> BoxedStrategy<String> {
+        prop::collection::vec("[[:alnum:]]{1,45}", 1..max_words)
+            .prop_map(|v| v.join(" "))
+            .boxed()
+    }
+
+    #[test]
+    fn test_parser_reuse() {
+        let messages = ["Hello *world*\n", "# Title\n\n* item\n", "", "`code`"];
+        let mut parser = Parser::new();
+
+        for message in &messages {
+            let (first, second) = message.split_at(message.len() / 2);
+            parser.feed(first);
+            parser.feed(second);
+            let root = parser.finish_and_reset();
+
+            assert!(root.renders_same(&parse_document(message)));
+            match root {
+                Node::Document(ref document) => assert_eq!(document.get_source(), Some(*message)),
+                _ => panic!("Expected a Document node"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let body = "\
+        # My New Document
+        ";
+        let node = parse_document(body);
+
+        match node {
+            Node::Document(_) => (),
+            _ => panic!("Did not get a Document Node after parsing."),
+        }
+    }
+
+    #[test]
+    fn test_equality() {
+        let body = "\
+        # My New Document
+        ";
+        let node = parse_document(body);
+        let other = node.itself().unwrap();
+
+        assert_eq!(node, other);
+    }
+
+    #[test]
+    fn test_inequality() {
+        let body = "\
+        # My New Document
+        ";
+        let node = parse_document(body);
+        let other = node.first_child()
+            .unwrap()
+            .expect("Root should have a child");
+
+        assert_ne!(node, other);
+    }
+
+    #[test]
+    fn test_root_node_gets_tracked() {
+        let body = "\
+        # My New Document
+        ";
+        let manager;
+        let pointer;
+        {
+            let node = parse_document(body);
+            manager = node.manager();
+            pointer = node.pointer();
+        }
+        assert!(manager.is_tracking(&pointer));
+    }
+
+    #[test]
+    fn test_iterator_hits_all_items() {
+        let body = "* Item 1\n* Item 2\n* Item 3";
+        let root = parse_document(body);
+        let mut node_contents: Vec<String> = Vec::new();
+        let mut item_count = 0;
+
+        for item in root.iter() {
+            match item {
+                (Node::Item(_), IterEventType::Enter) => item_count += 1,
+                (Node::Text(ref text), IterEventType::Enter) => {
+                    node_contents.push(text.get_content().unwrap())
+                }
+                _ => (),
+            }
+        }
+
+        assert_eq!(item_count, 3);
+        assert!(node_contents.contains(&String::from("Item 1")));
+        assert!(node_contents.contains(&String::from("Item 2")));
+        assert!(node_contents.contains(&String::from("Item 3")));
+    }
+
+    #[test]
+    fn test_parent_child_traversal() {
+        let body = "* Item 1\n* Item 2\n* Item 3";
+        let root = parse_document(body);
+        let child = root.first_child()
+            .unwrap()
+            .expect("Root should have had child");
+        assert_eq!(
+            root,
+            child
+                .parent()
+                .unwrap()
+                .expect("Child should have had a parent")
+        );
+    }
+
+    #[test]
+    fn test_sibling_traversal() {
+        let body = "* Item 1\n* Item 2\n* Item 3";
+        let root = parse_document(body);
+        let list = root.first_child()
+            .unwrap()
+            .expect("Root should have had list");
+        let first_item = list.first_child()
+            .unwrap()
+            .expect("List should have had item");
+        let next_item = first_item
+            .next_sibling()
+            .unwrap()
+            .expect("First item should have had next sibling");
+
+        assert_eq!(
+            first_item,
+            next_item
+                .prev_sibling()
+                .unwrap()
+                .expect("Next item should have had prev item")
+        );
+    }
+
+    #[test]
+    fn test_following_and_preceding_siblings() {
+        let body = "* Item 1\n* Item 2\n* Item 3";
+        let root = parse_document(body);
+        let middle_item = root.first_child()
+            .unwrap()
+            .expect("Root should have had list")
+            .first_child()
+            .unwrap()
+            .expect("List should have had item")
+            .next_sibling()
+            .unwrap()
+            .expect("First item should have had next sibling");
+        let last_item = middle_item.next_sibling().unwrap().unwrap();
+        let first_item = middle_item.prev_sibling().unwrap().unwrap();
+
+        let following: Vec<Node> = middle_item.following_siblings().collect();
+        let preceding: Vec<Node> = middle_item.preceding_siblings().collect();
+
+        assert_eq!(following, vec![last_item]);
+        assert_eq!(preceding, vec![first_item]);
+    }
+
+    #[test]
+    fn parse_and_render() {
+        let content = "# Testing";
+        let root = parse_document(content);
+
+        assert_eq!(content, root.render_commonmark().trim());
+    }
+
+    #[test]
+    fn test_convenience_appenders() {
+        let mut root = Node::from_type(NodeType::CMarkNodeDocument).unwrap();
+
+        root.append_heading(2, "My Great Document").unwrap();
+        root.append_paragraph_text("Some content").unwrap();
+        root.append_code_block("rust", "let x = 1;\n").unwrap();
+
+        let rendered = root.render_commonmark();
+        assert!(rendered.starts_with("## My Great Document\n\nSome content\n\n"));
+        assert!(rendered.contains("rust\nlet x = 1;\n"));
+    }
+
+    #[test]
+    fn test_list_from_items() {
+        let list = Node::List(List::from_items(&["a", "b", "c"]).unwrap());
+        let rendered = list.render_commonmark();
+        let bullets: Vec<&str> = rendered
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with("- "))
+            .collect();
+
+        assert_eq!(bullets, vec!["- a", "- b", "- c"]);
+    }
+
+    #[test]
+    fn test_list_from_ordered_items() {
+        let list = List::from_ordered_items(&["a", "b"]).unwrap();
+        assert!(list.get_list_type().unwrap() == ListType::CMarkOrderedList);
+        assert_eq!(list.get_list_start(), 1);
+
+        let rendered = Node::List(list).render_commonmark();
+        assert!(rendered.contains("1. a"));
+        assert!(rendered.contains("2. b"));
+    }
+
+    #[test]
+    fn test_render_commonmark_trimmed() {
+        let root = parse_document("# Testing");
+
+        assert_eq!(root.render_commonmark_trimmed(), "# Testing");
+        assert_eq!(parse_document("").render_commonmark_trimmed(), "");
+    }
+
+    #[test]
+    fn test_normalize_bullet_marker() {
+        let root = parse_document("* *one*\n  + two\n\n> - three\n\n1. four\n");
+        let rendered = root.normalize_bullet_marker('*').unwrap();
+
+        assert!(!rendered.contains('-'));
+        assert_eq!(rendered.matches("* ").count(), 3);
+        assert!(rendered.contains("*one*"));
+        assert!(rendered.contains("1. four") || rendered.contains("1.  four"));
+        assert!(parse_document(&rendered).renders_same(&root));
+        match root.normalize_bullet_marker('#') {
+            Err(DoogieError::InvalidArgument(_)) => (),
+            _ => panic!("Expected an InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    fn test_render_commonmark_preserve_code() {
+        let content =
+            "Title\n=====\n\n```text\ncode  \n\tindented\n```\n\n> ~~~\n> quoted \n> ~~~\n";
+        let root = parse_document(content);
+
+        assert_eq!(
+            root.render_commonmark_preserve_code().unwrap(),
+            "# Title\n\n```text\ncode  \n\tindented\n```\n\n> ~~~\n> quoted \n> ~~~\n"
+        );
+
+        let mut block = root.at_path(&[1]).unwrap().unwrap();
+        if let Node::CodeBlock(ref mut block) = block {
+            block.set_content(&"changed\n".to_string()).unwrap();
+        }
+        let rendered = root.render_commonmark_preserve_code().unwrap();
+        assert!(rendered.contains("changed"));
+        assert!(!rendered.contains("indented"));
+        assert!(rendered.ends_with("> ~~~\n> quoted \n> ~~~\n"));
+    }
+
+    #[test]
+    fn test_inner_text() {
+        let root = parse_document("# A *great* `title`\n\nFirst\nline\n");
+
+        assert_eq!(root.inner_text().unwrap(), "A great title\nFirst line");
+    }
+
+    #[test]
+    fn test_deep_copy() {
+        let root = parse_document("# Title\n\n* [link](http://example.com \"title\")\n");
+        let copy = root.deep_copy().unwrap();
+
+        assert_ne!(root, copy);
+        assert_eq!(root.render_commonmark(), copy.render_commonmark());
+    }
+
+    #[test]
+    fn test_section() {
+        let body = "# Readme\n\nIntro\n\n## Install\n\nRun cargo\n\n### From source\n\n\
+                    Clone it\n\n## Usage\n\nUse it\n";
+        let root = parse_document(body);
+        let section = root.section("Install")
+            .unwrap()
+            .expect("Install section should have been found");
+        let rendered = section.render_commonmark();
+
+        assert!(rendered.starts_with("## Install"));
+        assert!(rendered.contains("Run cargo"));
+        assert!(rendered.contains("### From source"));
+        assert!(rendered.contains("Clone it"));
+        assert!(!rendered.contains("Intro"));
+        assert!(!rendered.contains("Usage"));
+        assert!(root.section("Missing").unwrap().is_none());
+        assert_eq!(
+            root.section(" Usage\n")
+                .unwrap()
+                .unwrap()
+                .render_commonmark(),
+            "## Usage\n\nUse it\n"
+        );
+    }
+
+    #[test]
+    fn test_source_text() {
+        let body = "# Tïtlé\n\nSome *émph* text\n\n> quoted\n> lines\n";
+        let root = parse_document(body);
+        let heading = root.first_child().unwrap().unwrap();
+        let paragraph = heading.next_sibling().unwrap().unwrap();
+        let quote = paragraph.next_sibling().unwrap().unwrap();
+
+        match root {
+            Node::Document(ref document) => assert_eq!(document.get_source(), Some(body)),
+            _ => panic!("Root should have been a Document"),
+        }
+        assert_eq!(heading.source_text().unwrap(), Some("# Tïtlé".to_string()));
+        assert_eq!(
+            paragraph.source_text().unwrap(),
+            Some("Some *émph* text".to_string())
+        );
+        assert_eq!(
+            quote.source_text().unwrap(),
+            Some("> quoted\n> lines".to_string())
+        );
+
+        let text = Node::Text(Text::new());
+        assert_eq!(text.source_text().unwrap(), None);
+    }
+
+    #[test]
+    fn test_source_of_moved_nodes() {
+        let mut root = parse_document("First\n\n* a\n\n* b\n");
+        let mut heading = parse_document("# Elsewhere\n")
+            .first_child()
+            .unwrap()
+            .unwrap();
+        let mut item = parse_document("* c\n").at_path(&[0, 0]).unwrap().unwrap();
+
+        root.append_child(&mut heading).unwrap();
+        root.at_path(&[1])
+            .unwrap()
+            .unwrap()
+            .append_child(&mut item)
+            .unwrap();
+
+        let moved = root.last_child().unwrap().unwrap();
+        assert_eq!(moved.source_text().unwrap(), None);
+        assert_eq!(moved.get_start_byte(), None);
+        assert!(moved.as_heading().unwrap().style().is_err());
+        assert_eq!(
+            heading.source_text().unwrap(),
+            Some("# Elsewhere".to_string())
+        );
+        assert_eq!(
+            root.first_child().unwrap().unwrap().source_text().unwrap(),
+            Some("First".to_string())
+        );
+
+        let list = root.at_path(&[1]).unwrap().unwrap();
+        match list.as_list().unwrap().loose_items() {
+            Err(DoogieError::SourceUnavailable) => (),
+            _ => panic!("A list with a moved item should not have had source"),
+        }
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        let root = parse_document("héllo\nwörld\n");
+        let document = match root {
+            Node::Document(document) => document,
+            _ => panic!("Root should have been a Document"),
+        };
+
+        assert_eq!(document.byte_offset(1, 1), Some(0));
+        assert_eq!(document.byte_offset(1, 3), Some(3));
+        assert_eq!(document.byte_offset(1, 6), Some(6));
+        assert_eq!(document.byte_offset(1, 7), None);
+        assert_eq!(document.byte_offset(2, 3), Some(10));
+        assert_eq!(document.byte_offset(3, 1), Some(14));
+        assert_eq!(document.byte_offset(4, 1), None);
+        assert_eq!(document.byte_offset(0, 1), None);
+        assert_eq!(Document::new().byte_offset(1, 1), None);
+    }
+
+    #[test]
+    fn test_start_and_end_bytes() {
+        let body = "Hello 🎉 world\n\nSecond *paragraph*\n";
+        let root = parse_document(body);
+        let second = root.last_child().unwrap().unwrap();
+        let emphasis = second.last_child().unwrap().unwrap();
+
+        assert_eq!(second.get_start_byte(), Some(18));
+        assert_eq!(second.get_end_byte(), Some(36));
+        assert_eq!(&body[18..36], "Second *paragraph*");
+        assert_eq!(emphasis.get_start_byte(), Some(25));
+        assert_eq!(emphasis.get_end_byte(), Some(36));
+
+        let text = Node::Text(Text::new());
+        assert_eq!(text.get_start_byte(), None);
+        assert_eq!(text.get_end_byte(), None);
+    }
+
+    #[test]
+    fn test_code_block_is_fenced() {
+        let root = parse_document("```rust\nlet x = 1;\n```\n\n    indented\n");
+        let fenced = root.first_child().unwrap().unwrap();
+        let indented = root.last_child().unwrap().unwrap();
+
+        match (fenced, indented) {
+            (Node::CodeBlock(fenced), Node::CodeBlock(indented)) => {
+                assert!(fenced.is_fenced());
+                assert!(!indented.is_fenced());
+            }
+            _ => panic!("Both blocks should have been Code Blocks"),
+        }
+
+        let root = parse_document("```a\n```a\n```\n\n    ```a\n    foo\n    ```\n");
+        match (root.first_child().unwrap(), root.last_child().unwrap()) {
+            (Some(Node::CodeBlock(fenced)), Some(Node::CodeBlock(indented))) => {
+                assert!(fenced.is_fenced());
+                assert!(!indented.is_fenced());
+            }
+            _ => panic!("Both blocks should have been Code Blocks"),
+        }
+
+        let mut constructed = CodeBlock::new();
+        assert!(!constructed.is_fenced());
+        constructed.set_fence_info(&String::from("rust")).unwrap();
+        assert!(!constructed.is_fenced());
+    }
+
+    #[test]
+    fn test_code_block_fence_details() {
+        let root = parse_document("````md\n```\n````\n\n  ~~~\ntilde\n  ~~~\n\n    indented\n");
+        let mut blocks = root.children().unwrap().into_iter();
+
+        match blocks.next() {
+            Some(Node::CodeBlock(block)) => {
+                assert_eq!(block.fence_details().unwrap(), Some(('`', 4, 0)));
+                assert_eq!(block.get_content().unwrap(), "```\n");
+            }
+            _ => panic!("First block should have been a Code Block"),
+        }
+        match blocks.next() {
+            Some(Node::CodeBlock(block)) => {
+                assert_eq!(block.fence_details().unwrap(), Some(('~', 3, 2)));
+            }
+            _ => panic!("Second block should have been a Code Block"),
+        }
+        match blocks.next() {
+            Some(Node::CodeBlock(block)) => assert_eq!(block.fence_details().unwrap(), None),
+            _ => panic!("Third block should have been a Code Block"),
+        }
+    }
+
+    #[test]
+    fn test_code_blocks_for_language() {
+        let body = "```rust\nfn a() {}\n```\n\n```python\ndef b(): pass\n```\n\n\
+                    > ```rust,ignore\n> fn c() {}\n> ```\n\n```rusty\nd\n```\n";
+        let root = parse_document(body);
+
+        assert_eq!(
+            root.code_blocks_for_language("rust").unwrap(),
+            vec!["fn a() {}\n".to_string(), "fn c() {}\n".to_string()]
+        );
+        assert_eq!(
+            root.code_blocks_for_language("python").unwrap(),
+            vec!["def b(): pass\n".to_string()]
+        );
+        assert!(root.code_blocks_for_language("go").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_iter_rev() {
+        let root = parse_document("# Title\n\n* one\n* *two*\n\nLast `code`\n");
+        let mut forward: Vec<Node> = root
+            .iter()
+            .filter(|&(_, ref event)| *event == IterEventType::Enter)
+            .map(|(node, _)| node)
+            .collect();
+        forward.reverse();
+
+        let reverse: Vec<Node> = root.iter_rev().collect();
+        assert_eq!(reverse, forward);
+        assert_eq!(reverse.last(), Some(&root));
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let expected: Vec<NodeType> = parse_document("# Title\n\nSome *text*\n")
+            .iter()
+            .map(|(node, _)| node.get_cmark_type().unwrap())
+            .collect();
+
+        let mut types = Vec::new();
+        for (node, event) in parse_document("# Title\n\nSome *text*\n") {
+            types.push(node.get_cmark_type().unwrap());
+            if let (Node::Heading(mut heading), IterEventType::Enter) = (node, event) {
+                heading.set_level(2).unwrap();
+            }
+        }
+
+        assert_eq!(types, expected);
+    }
+
+    #[test]
+    fn test_to_owned_document() {
+        let (quote, emph) = {
+            let root = parse_document("> A *quoted* paragraph\n");
+            let quote = root.first_child().unwrap().unwrap();
+            let paragraph = quote.first_child().unwrap().unwrap();
+            let emph = paragraph.children().unwrap().remove(1);
+
+            (
+                quote.to_owned_document().unwrap(),
+                emph.to_owned_document().unwrap(),
+            )
+        };
+
+        assert_eq!(quote.get_cmark_type().unwrap(), NodeType::CMarkNodeDocument);
+        assert_eq!(quote.render_commonmark(), "> A *quoted* paragraph\n");
+        assert_eq!(emph.render_commonmark(), "*quoted*\n");
+    }
+
+    #[test]
+    fn test_append_document() {
+        let mut book = parse_document("# Chapter 1\n\nIntro\n");
+        {
+            let mut chapter = parse_document("# Chapter 2\n\nBody\n\nOutro\n");
+            book.append_document(&mut chapter).unwrap();
+            assert_eq!(chapter.first_child().unwrap(), None);
+        }
+
+        assert_eq!(book.children().unwrap().len(), 5);
+        assert_eq!(
+            book.render_commonmark(),
+            "# Chapter 1\n\nIntro\n\n# Chapter 2\n\nBody\n\nOutro\n"
+        );
+    }
+
+    #[test]
+    fn test_rebase_links() {
+        let body = "[a](guide.html) [b](../img/x.png) [c](/root.html) [d](https://x.org/) \
+                    [e](#usage) [f](mailto:a@b.c) ![g](./logo.png)\n";
+        let mut root = parse_document(body);
+
+        assert_eq!(
+            root.rebase_links("https://example.com/docs/page.html")
+                .unwrap(),
+            4
+        );
+
+        let urls: Vec<String> = root
+            .iter()
+            .filter_map(|(node, event)| match (node, event) {
+                (Node::Link(link), IterEventType::Enter) => Some(link.get_url().unwrap()),
+                (Node::Image(image), IterEventType::Enter) => Some(image.get_url().unwrap()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://example.com/docs/guide.html",
+                "https://example.com/img/x.png",
+                "https://example.com/root.html",
+                "https://x.org/",
+                "#usage",
+                "mailto:a@b.c",
+                "https://example.com/docs/logo.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_undefined_references() {
+        let body = "See [the guide][guide], [missing][], [Typo] and [defined].\n\n\
+                    Not \\[escaped\\] nor [inline](\n\n\
+                    A [full\nlabel][other ref] spans lines.\n\n\
+                    [defined]: https://example.com\n";
+        let root = parse_document(body);
+
+        assert_eq!(
+            root.find_undefined_references().unwrap(),
+            vec!["guide", "missing", "Typo", "other ref"]
+        );
+
+        let root = parse_document("Read array[0] and map[key][], then [sic] it.\n");
+        assert_eq!(
+            root.find_undefined_references().unwrap(),
+            vec!["key", "sic"]
+        );
+    }
+
+    #[test]
+    fn test_parse_document_with_frontmatter() {
+        let (frontmatter, root) =
+            parse_document_with_frontmatter("---\ntitle: Doc\ntags: [a]\n---\n# Title\n\n---\n")
+                .unwrap();
+        assert_eq!(frontmatter, Some("title: Doc\ntags: [a]\n".to_string()));
+        assert_eq!(root.render_commonmark(), "# Title\n\n-----\n");
+
+        let (frontmatter, root) =
+            parse_document_with_frontmatter("# Title\n\n---\na\n---\n").unwrap();
+        assert_eq!(frontmatter, None);
+        assert_eq!(
+            root.first_child()
+                .unwrap()
+                .unwrap()
+                .get_cmark_type()
+                .unwrap(),
+            NodeType::CMarkNodeHeading
+        );
+
+        let (frontmatter, _) = parse_document_with_frontmatter("---\nnever closed\n").unwrap();
+        assert_eq!(frontmatter, None);
+    }
+
+    #[test]
+    fn test_child_of_type() {
+        let root = parse_document("* First\n\n  * nested\n\n  Second\n");
+        let list = root.first_child().unwrap().unwrap();
+        let item = list.first_child().unwrap().unwrap();
+
+        let first = item
+            .first_child_of_type(NodeType::CMarkNodeParagraph)
+            .unwrap()
+            .unwrap();
+        let last = item
+            .last_child_of_type(NodeType::CMarkNodeParagraph)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.inner_text().unwrap(), "First");
+        assert_eq!(last.inner_text().unwrap(), "Second");
+        assert!(item
+            .first_child_of_type(NodeType::CMarkNodeList)
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            item.first_child_of_type(NodeType::CMarkNodeHeading)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_heading() {
+        let root = parse_document("# Doogie\n\n> ## *Usage* notes\n\n### Usage\n\nUsage\n");
+
+        match root.find_heading("Usage").unwrap() {
+            Some(Node::Heading(heading)) => assert_eq!(heading.get_level(), 3),
+            _ => panic!("The Usage heading should have been found"),
+        }
+        match root.find_heading(" Usage notes ").unwrap() {
+            Some(Node::Heading(heading)) => assert_eq!(heading.get_level(), 2),
+            _ => panic!("The nested heading should have been found"),
+        }
+        assert_eq!(root.find_heading("usage").unwrap(), None);
+    }
+
+    #[test]
+    fn test_reading_time() {
+        let words = vec!["word"; 450].join(" ");
+        let body = format!("# Two words\n\n{}\n\n```\nnot counted\n```\n", words);
+        let root = parse_document(&body);
+
+        assert_eq!(root.word_count().unwrap(), 452);
+        assert_eq!(
+            root.reading_time(200).unwrap(),
+            Duration::from_millis(135_600)
+        );
+        match root.reading_time(0) {
+            Err(DoogieError::InvalidArgument(_)) => (),
+            _ => panic!("A reading speed of zero should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let body = "# Café\n\nSee [the docs](a) and ![logo](b).\n\n```\ncode\n```\n\n## End\n";
+        let root = parse_document(body);
+        let stats = root.statistics().unwrap();
+
+        assert_eq!(
+            stats,
+            DocStats {
+                words: 7,
+                characters: root.inner_text().unwrap().chars().count(),
+                headings: 2,
+                links: 1,
+                images: 1,
+                code_blocks: 1,
+            }
+        );
+        assert_eq!(stats.characters, 31);
+        assert_eq!(stats.words, root.word_count().unwrap());
+    }
+
+    #[test]
+    fn test_outline() {
+        let root = parse_document("# A\n\n## B\n\n## C\n\n#### D\n\n# E\n");
+        let leaf = |level: usize, title: &str| OutlineNode {
+            level,
+            title: title.to_string(),
+            children: Vec::new(),
+        };
+
+        let mut c = leaf(2, "C");
+        c.children.push(leaf(4, "D"));
+        let mut a = leaf(1, "A");
+        a.children.push(leaf(2, "B"));
+        a.children.push(c);
+
+        assert_eq!(root.outline().unwrap(), vec![a, leaf(1, "E")]);
+    }
+
+    #[test]
+    fn test_add_heading_anchors() {
+        let mut root = parse_document("# Getting Started, Now\n\n## Usage\n\n## Usage\n\nText\n");
+
+        let count = root.add_heading_anchors("<a id=\"{slug}\"></a>").unwrap();
+        let rendered = root.render_commonmark();
+
+        assert_eq!(count, 3);
+        assert!(rendered.starts_with("# <a id=\"getting-started-now\"></a>Getting Started, Now\n"));
+        assert!(rendered.contains("## <a id=\"usage\"></a>Usage\n"));
+        assert!(rendered.contains("## <a id=\"usage-1\"></a>Usage\n"));
+
+        let html = root.render_html_with_options(DoogieOptions::UNSAFE);
+        assert!(html.contains("<h1><a id=\"getting-started-now\"></a>Getting Started, Now</h1>"));
+        assert!(html.contains("<h2><a id=\"usage-1\"></a>Usage</h2>"));
+    }
+
+    #[test]
+    fn test_close() {
+        let root = parse_document("# Title\n\nSome text\n");
+        let heading = root.first_child().unwrap().unwrap();
+
+        root.close().unwrap();
+
+        match heading.get_cmark_type() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("The heading should have been unavailable"),
+        }
+        match heading.next_sibling() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("The heading should have been unavailable"),
+        }
+        assert_eq!(heading.iter().count(), 0);
+        assert_eq!(heading.as_heading().unwrap().get_level(), 0);
+        assert_eq!(heading.get_end_line(), 0);
+        match heading.close() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("Closing twice should have failed"),
+        }
+
+        Node::from_type(NodeType::CMarkNodeParagraph)
+            .unwrap()
+            .close()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_freed_node_guard() {
+        let root = parse_document("# Title\n\nSome *text*\n");
+        let mut heading = root.first_child().unwrap().unwrap();
+        let text = root.at_path(&[1, 0]).unwrap().unwrap();
+
+        root.close().unwrap();
+
+        assert_eq!(heading.render_commonmark(), "");
+        assert!(heading.render_xml_bytes().is_empty());
+        assert!(heading.render_html_bytes().is_empty());
+        match text.as_text().unwrap().get_content() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("Reading the content of a freed node should have failed"),
+        }
+        assert_eq!(text.as_text().unwrap().get_content_lossy(), "");
+        let mut emph = Node::from_type(NodeType::CMarkNodeEmph).unwrap();
+        match heading.append_child(&mut emph) {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("Appending to a freed node should have failed"),
+        }
+        heading.unlink();
+        assert!(!heading.has_children());
+    }
+
+    #[test]
+    fn test_close_after_move() {
+        let mut root = parse_document("Intro\n");
+        let other = parse_document("# Moved\n\nLeft behind\n");
+        let mut heading = other.first_child().unwrap().unwrap();
+        let text = heading.first_child().unwrap().unwrap();
+
+        root.append_child(&mut heading).unwrap();
+        assert_eq!(root.tracked_root_count(), 2);
+        root.close().unwrap();
+
+        match heading.get_cmark_type() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("The moved heading should have been unavailable"),
+        }
+        assert_eq!(heading.render_commonmark(), "");
+        assert_eq!(text.as_text().unwrap().get_content_lossy(), "");
+        assert_eq!(other.render_commonmark(), "");
+
+        let mut root = parse_document("Intro\n");
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        root.append_child(&mut paragraph).unwrap();
+        drop(root);
+        let parent = paragraph.parent().unwrap().unwrap();
+        assert!(parent.render_commonmark().starts_with("Intro\n"));
+    }
+
+    #[test]
+    fn test_tracked_root_count() {
+        let root = parse_document("* a\n* b\n");
+        let mut list = root.first_child().unwrap().unwrap();
+        let mut item = list.last_child().unwrap().unwrap();
+        assert_eq!(root.tracked_root_count(), 1);
+
+        item.unlink();
+        assert_eq!(root.tracked_root_count(), 2);
+        assert_eq!(item.tracked_root_count(), 2);
+
+        list.append_child(&mut item).unwrap();
+        assert_eq!(root.tracked_root_count(), 1);
+    }
+
+    #[test]
+    fn test_from_borrowed_raw() {
+        let pointer: *mut CMarkNodePtr;
+        let child: *mut CMarkNodePtr;
+        unsafe {
+            pointer = cmark_node_new(NodeType::CMarkNodeParagraph as u32);
+            child = cmark_node_new(NodeType::CMarkNodeText as u32);
+            cmark_node_append_child(pointer, child);
+        }
+
+        {
+            let node = unsafe { Node::from_borrowed_raw(pointer).unwrap() };
+            let mut text = node.first_child().unwrap().unwrap();
+            text.unlink();
+            assert_eq!(node.tracked_root_count(), 1);
+        }
+
+        unsafe {
+            assert_eq!(
+                cmark_node_get_type(pointer),
+                NodeType::CMarkNodeParagraph as i32
+            );
+            assert_eq!(cmark_node_get_type(child), NodeType::CMarkNodeText as i32);
+            cmark_node_free(pointer);
+            cmark_node_free(child);
+            assert!(Node::from_borrowed_raw(std::ptr::null_mut()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_as_ptr() {
+        let root = parse_document("Some text\n");
+        let paragraph = root.first_child().unwrap().unwrap();
+
+        unsafe {
+            assert_eq!(
+                cmark_node_get_type(root.as_ptr()),
+                NodeType::CMarkNodeDocument as i32
+            );
+            assert_eq!(cmark_node_first_child(root.as_ptr()), paragraph.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_document_from_raw() {
+        let body = "# Raw\n";
+        let pointer;
+        let paragraph;
+        unsafe {
+            pointer = cmark_parse_document(body.as_ptr(), body.len(), 0);
+            paragraph = cmark_node_new(NodeType::CMarkNodeParagraph as u32);
+        }
+
+        let document = unsafe { Document::from_raw(pointer).unwrap() };
+        assert_eq!(Node::Document(document).render_commonmark(), "# Raw\n");
+
+        match unsafe { Document::from_raw(paragraph) } {
+            Err(DoogieError::WrongNodeType { expected, found }) => {
+                assert_eq!(expected, NodeType::CMarkNodeDocument);
+                assert_eq!(found, NodeType::CMarkNodeParagraph);
+            }
+            _ => panic!("A Paragraph should not have been wrapped as a Document"),
+        }
+        unsafe {
+            cmark_node_free(paragraph);
+        }
+    }
+
+    #[test]
+    fn test_get_content_lossy() {
+        // libcmark replaces invalid UTF-8 while parsing, so the literals are set directly
+        let text = Text::new();
+        let code = Code::new();
+        let block = CodeBlock::new();
+        unsafe {
+            cmark_node_set_literal(
+                text.resource.pointer,
+                b"caf\xe9\0".as_ptr() as *const c_char,
+            );
+            cmark_node_set_literal(code.resource.pointer, b"\xff\0".as_ptr() as *const c_char);
+            cmark_node_set_literal(
+                block.resource.pointer,
+                b"\xfe\n\0".as_ptr() as *const c_char,
+            );
+        }
+
+        assert!(text.get_content().is_err());
+        assert_eq!(text.get_content_lossy(), "caf\u{fffd}");
+        assert_eq!(code.get_content_lossy(), "\u{fffd}");
+        assert_eq!(block.get_content_lossy(), "\u{fffd}\n");
+        assert_eq!(Text::new().get_content_lossy(), "");
+    }
+
+    #[test]
+    fn test_render_html() {
+        let root = parse_document("# Title\n\n* one\n* two\n\n```rust\nlet x = 1;\n```\n");
+        let html = root.render_html();
+
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<ul>\n<li>one</li>\n<li>two</li>\n</ul>"));
+        assert!(html.contains("<pre><code class=\"language-rust\">let x = 1;\n</code></pre>"));
+        assert_eq!(
+            root.first_child().unwrap().unwrap().render_html(),
+            "<h1>Title</h1>\n"
+        );
+        assert_eq!(root.render_html_bytes(), html.into_bytes());
+    }
+
+    #[test]
+    fn test_options() {
+        let content = "# Title\n\nFirst\nsecond \"quote\"\n\n<div>raw</div>\n";
+        let root = parse_document_with_options(content, DoogieOptions::SOURCEPOS);
+        let paragraph = root.at_path(&[1]).unwrap().unwrap();
+
+        assert_eq!(
+            (paragraph.get_start_line(), paragraph.get_start_column()),
+            (3, 1)
+        );
+        assert_eq!(
+            (paragraph.get_end_line(), paragraph.get_end_column()),
+            (4, 14)
+        );
+        assert!(root
+            .render_xml_with_options(DoogieOptions::SOURCEPOS)
+            .contains("<heading sourcepos=\"1:1-1:7\" level=\"1\">"));
+        assert!(root
+            .render_html_with_options(DoogieOptions::SOURCEPOS)
+            .contains("<h1 data-sourcepos=\"1:1-1:7\">Title</h1>"));
+        assert!(!root.render_xml().contains("sourcepos"));
+
+        let html = root.render_html_with_options(DoogieOptions::HARDBREAKS | DoogieOptions::UNSAFE);
+        assert!(html.contains("First<br />"));
+        assert!(html.contains("<div>raw</div>"));
+
+        let smart = parse_document_with_options(content, DoogieOptions::SMART);
+        assert!(smart
+            .inner_text()
+            .unwrap()
+            .contains("\u{201c}quote\u{201d}"));
+        assert!(parse_document(content)
+            .inner_text()
+            .unwrap()
+            .contains("\"quote\""));
+
+        let mut options = DoogieOptions::empty();
+        options |= DoogieOptions::SMART;
+        assert!(options.contains(DoogieOptions::SMART));
+        assert!(!options.contains(DoogieOptions::SMART | DoogieOptions::SOURCEPOS));
+        assert_eq!(DoogieOptions::default().bits(), 0);
+    }
+
+    #[test]
+    fn test_render_bytes() {
+        let root = parse_document("# Tïtle\n\nSome *text* 🎉\n");
+
+        assert_eq!(
+            root.render_commonmark_bytes(),
+            root.render_commonmark().into_bytes()
+        );
+        assert_eq!(root.render_xml_bytes(), root.render_xml().into_bytes());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = parse_document("# Title\n\nOne *two*\n\nThree\n");
+        let new = parse_document("# Title\n\nOne *two*\n\nInserted\n\nThree\n");
+
+        assert_eq!(
+            diff(&old, &new).unwrap(),
+            vec![TreeEdit::Insert { path: vec![2] }]
+        );
+        assert_eq!(
+            diff(&new, &old).unwrap(),
+            vec![TreeEdit::Remove { path: vec![2] }]
+        );
+
+        let changed = parse_document("## Title\n\nOne *three*\n\nThree\n");
+        assert_eq!(
+            diff(&old, &changed).unwrap(),
+            vec![
+                TreeEdit::Change {
+                    old_path: vec![0],
+                    new_path: vec![0],
+                },
+                TreeEdit::Change {
+                    old_path: vec![1, 1, 0],
+                    new_path: vec![1, 1, 0],
+                },
+            ]
+        );
+        assert!(diff(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let body = "# Title \"quoted\" & <more>\n\n\
+                    1) *One* [link](http://a.b/?x=1&y=2 \"t\")\n2) **Two** ![img](i.png)\\\n   `code`\n\n\
+                    > - loose\n>\n> - list\n\n\
+                    ```rust\nlet x = \"<&>\";\n```\n\n\
+                    <div>\nhtml\n</div>\n\n\
+                    ---\n\ninline <b>html</b> and é\n";
+        let root = parse_document(body);
+
+        let copy = parse_xml(&root.render_xml()).unwrap();
+
+        assert_eq!(copy.get_cmark_type().unwrap(), NodeType::CMarkNodeDocument);
+        assert_eq!(copy.render_xml(), root.render_xml());
+        assert_eq!(copy.render_commonmark(), root.render_commonmark());
+        assert_eq!(copy.tracked_root_count(), 1);
+
+        match parse_xml("<document><paragraph></document>") {
+            Err(DoogieError::XmlError(_)) => (),
+            _ => panic!("Mismatched tags should have been rejected"),
+        }
+        match parse_xml("<document><table /></document>") {
+            Err(DoogieError::XmlError(_)) => (),
+            _ => panic!("Unknown elements should have been rejected"),
+        }
+        match parse_xml("<document><\u{3000}paragraph /></document>") {
+            Err(DoogieError::XmlError(_)) => (),
+            _ => panic!("Whitespace before an element name should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn test_at_path() {
+        let root = parse_document("# Title\n\n* first\n* second\n* third\n");
+
+        let item = root.at_path(&[1, 1]).unwrap().unwrap();
+        assert_eq!(item.get_cmark_type().unwrap(), NodeType::CMarkNodeItem);
+        assert_eq!(item.inner_text().unwrap(), "second");
+        assert_eq!(root.at_path(&[]).unwrap(), Some(root.itself().unwrap()));
+        assert_eq!(root.at_path(&[1, 3]).unwrap(), None);
+        assert_eq!(root.at_path(&[0, 0, 0]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_node_at_line() {
+        let root = parse_document("# Title\n\n> Quoted\n>\n> ```\n> code\n> more\n> ```\n\nEnd\n");
+
+        match root.node_at_line(6).unwrap() {
+            Some(Node::CodeBlock(block)) => {
+                assert_eq!(block.get_content().unwrap(), "code\nmore\n")
+            }
+            _ => panic!("Line 6 should have been in the Code Block"),
+        }
+        match root.node_at_line(3).unwrap() {
+            Some(Node::Text(text)) => assert_eq!(text.get_content().unwrap(), "Quoted"),
+            _ => panic!("Line 3 should have been in the quoted Text"),
+        }
+        match root.node_at_line(4).unwrap() {
+            Some(Node::BlockQuote(_)) => (),
+            _ => panic!("Line 4 should only have been in the Block Quote"),
+        }
+        assert_eq!(root.node_at_line(42).unwrap(), None);
+    }
+
+    #[test]
+    fn test_observe_mutations() {
+        let root = parse_document("* a\n* b\n\n*c*\n");
+        let mut list = root.first_child().unwrap().unwrap();
+        let mut item = list.first_child().unwrap().unwrap();
+        let mut emph = root.at_path(&[1, 0]).unwrap().unwrap();
+        let text = emph.first_child().unwrap().unwrap();
+        let paragraph = emph.parent().unwrap().unwrap();
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        root.observe_mutations(move |event| recorded.borrow_mut().push(event));
+
+        item.unlink();
+        item.unlink();
+        list.append_child(&mut item).unwrap();
+        emph.unwrap().unwrap();
+        root.stop_observing_mutations();
+        list.unlink();
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                MutationEvent::Unlink {
+                    node: item.event_id(),
+                    parent: list.event_id(),
+                },
+                MutationEvent::AppendChild {
+                    parent: list.event_id(),
+                    child: item.event_id(),
+                },
+                MutationEvent::Unlink {
+                    node: text.event_id(),
+                    parent: emph.event_id(),
+                },
+                MutationEvent::InsertBefore {
+                    node: text.event_id(),
+                    before: emph.event_id(),
+                },
+                MutationEvent::Unlink {
+                    node: emph.event_id(),
+                    parent: paragraph.event_id(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detach() {
+        let root = parse_document("* one\n* two\n");
+        let mut item = root.at_path(&[0, 1]).unwrap().unwrap();
+        let earlier = item.itself().unwrap();
+        let text = item.at_path(&[0, 0]).unwrap().unwrap();
+
+        let detached = item.detach().unwrap();
+        assert_eq!(root.tracked_root_count(), 2);
+        assert_eq!(root.render_commonmark(), "- one\n");
+        drop(root);
+
+        assert_eq!(detached.get_id(), item.get_id());
+        assert!(detached.parent().unwrap().is_none());
+        assert!(detached.render_commonmark().contains("two"));
+        drop(detached);
+        drop(item);
+        assert!(earlier.render_commonmark().contains("two"));
+        assert_eq!(text.as_text().unwrap().get_content().unwrap(), "two");
+
+        let root = parse_document("Text\n");
+        let mut itself = root.itself().unwrap();
+        let detached = itself.detach().unwrap();
+        drop(root);
+        drop(itself);
+        assert_eq!(detached.tracked_root_count(), 1);
+        assert_eq!(detached.render_commonmark(), "Text\n");
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        let mut root = parse_document("Intro\n");
+        let mut blocks = Node::parse_fragment("# H\n\npara").unwrap();
+
+        assert_eq!(blocks.len(), 2);
+        for block in &mut blocks {
+            root.append_child(block).unwrap();
+        }
+        drop(blocks);
+
+        assert_eq!(root.render_commonmark(), "Intro\n\n# H\n\npara\n");
+    }
+
+    #[test]
+    fn test_into_raw() {
+        let root = parse_document("# Title\n\nBody\n");
+        let heading = root.first_child().unwrap().unwrap();
+
+        let heading_pointer = heading.into_raw();
+        assert_eq!(root.tracked_root_count(), 1);
+        assert_eq!(root.render_commonmark(), "Body\n");
+
+        let root_pointer = root.into_raw();
+        unsafe {
+            assert_eq!(
+                cmark_node_get_type(root_pointer),
+                NodeType::CMarkNodeDocument as i32
+            );
+            assert_eq!(
+                cmark_node_get_type(heading_pointer),
+                NodeType::CMarkNodeHeading as i32
+            );
+            cmark_node_free(root_pointer);
+            cmark_node_free(heading_pointer);
+        }
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let node_pointer: *mut CMarkNodePtr;
+        unsafe {
+            node_pointer = cmark_node_new(NodeType::CMarkNodeParagraph as u32);
+        }
+
+        let node = Node::from_raw(node_pointer, Rc::new(ResourceManager::new())).unwrap();
+
+        match node {
+            Node::Paragraph(_) => (),
+            _ => panic!("Node should have been a paragraph"),
+        }
+    }
+
+    #[test]
+    fn test_node_keeps_tree_alive() {
+        let paragraph = {
+            let root = parse_document("Some text\n");
+            root.first_child().unwrap().unwrap()
+        };
+
+        assert_eq!(paragraph.render_commonmark(), "Some text\n");
+        let root = paragraph.parent().unwrap().unwrap();
+        assert_eq!(root.get_cmark_type().unwrap(), NodeType::CMarkNodeDocument);
+    }
+
+    #[test]
+    fn test_unlink() {
+        let body = "* Item 1\n* Item 2\n* Item 3";
+        let root = parse_document(body);
+        let mut first_item = root.first_child()
+            .unwrap()
+            .expect("Root should have first child")
+            .first_child()
+            .unwrap()
+            .expect("List should have first item");
+        let manager = first_item.manager();
+
+        first_item.unlink();
+
+        assert!(manager.is_tracking(&first_item.pointer()));
+        for (node, _) in root.iter() {
+            if let Node::Text(node) = node {
+                assert!(!node.get_content().unwrap().contains("Item 1"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_append_child() {
+        let mut root_node = Node::from_type(NodeType::CMarkNodeDocument).unwrap();
+        let mut child_node = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+
+        root_node.append_child(&mut child_node).unwrap();
+
+        assert!(!root_node.manager().is_tracking(&child_node.pointer()));
+        assert_eq!(
+            root_node
+                .first_child()
+                .unwrap()
+                .expect("Root should have child"),
+            child_node
+        );
+    }
+
+    #[test]
+    fn test_return_code_names_operation() {
+        let mut outer = Node::from_type(NodeType::CMarkNodeBlockQuote).unwrap();
+        let mut inner = Node::from_type(NodeType::CMarkNodeBlockQuote).unwrap();
+        outer.append_child(&mut inner).unwrap();
+
+        match inner.append_child(&mut outer) {
+            Err(err @ DoogieError::ReturnCode { .. }) => {
+                assert_eq!(format!("{}", err), "append_child failed with code 0")
+            }
+            _ => panic!("Appending a BlockQuote to its own child should have failed"),
+        }
+    }
+
+    #[test]
+    fn test_append_invalid_child() {
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        let mut document = Node::from_type(NodeType::CMarkNodeDocument).unwrap();
+
+        match paragraph.append_child(&mut document) {
+            Err(DoogieError::InvalidChild { parent, child }) => {
+                assert_eq!(parent, NodeType::CMarkNodeParagraph);
+                assert_eq!(child, NodeType::CMarkNodeDocument);
+            }
+            _ => panic!("Appending a Document to a Paragraph should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn test_unwrap() {
+        let root = parse_document("a *x* b");
+        let paragraph = root.first_child().unwrap().unwrap();
+        let mut emph = paragraph.children().unwrap().remove(1);
+        assert_eq!(emph.get_cmark_type().unwrap(), NodeType::CMarkNodeEmph);
+
+        emph.unwrap().unwrap();
+
+        let types: Vec<NodeType> = paragraph
+            .children()
+            .unwrap()
+            .iter()
+            .map(|child| child.get_cmark_type().unwrap())
+            .collect();
+        assert_eq!(types, vec![NodeType::CMarkNodeText; 3]);
+        let x = paragraph.children().unwrap().remove(1);
+        assert_eq!(x.inner_text().unwrap(), "x");
+        assert_eq!(x.parent().unwrap(), Some(paragraph.itself().unwrap()));
+        assert_eq!(emph.parent().unwrap(), None);
+        assert_eq!(root.render_commonmark(), "a x b\n");
+
+        match emph.unwrap() {
+            Err(DoogieError::NoParent) => (),
+            _ => panic!("Unwrapping a Node without a parent should have failed"),
+        }
+    }
+
+    #[test]
+    fn test_wrap_in() {
+        let root = parse_document("x");
+        let paragraph = root.first_child().unwrap().unwrap();
+        let mut text = paragraph.first_child().unwrap().unwrap();
+
+        text.wrap_in(Node::from_type(NodeType::CMarkNodeStrong).unwrap())
+            .unwrap();
+
+        let strong = paragraph.first_child().unwrap().unwrap();
+        assert_eq!(strong.get_cmark_type().unwrap(), NodeType::CMarkNodeStrong);
+        assert_eq!(text.parent().unwrap(), Some(strong));
+        assert_eq!(root.render_commonmark(), "**x**\n");
+
+        match text.wrap_in(Node::from_type(NodeType::CMarkNodeList).unwrap()) {
+            Err(DoogieError::InvalidChild { parent, child }) => {
+                assert_eq!(parent, NodeType::CMarkNodeList);
+                assert_eq!(child, NodeType::CMarkNodeText);
+            }
+            _ => panic!("Wrapping Text in a List should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn test_heading_style() {
+        let root = parse_document("# Atx\n\nSetext\n------\n\n#tag\n===\n\n## Closed ##\n");
+        let styles: Vec<HeadingStyle> = root
+            .iter()
+            .filter_map(|(node, event)| match (node, event) {
+                (Node::Heading(heading), IterEventType::Enter) => Some(heading.style().unwrap()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            styles,
+            vec![
+                HeadingStyle::Atx,
+                HeadingStyle::Setext,
+                HeadingStyle::Setext,
+                HeadingStyle::Atx,
+            ]
+        );
+        assert!(Heading::new().style().is_err());
+    }
+
+    #[test]
+    fn test_clear_content() {
+        let root = parse_document("Some *text*\n");
+        let mut text = root.at_path(&[0, 0]).unwrap().unwrap();
+        let mut emph = root.at_path(&[0, 1]).unwrap().unwrap();
+
+        text.clear_content().unwrap();
+        emph.clear_content().unwrap();
+
+        match text {
+            Node::Text(ref text) => assert_eq!(text.get_content().unwrap(), ""),
+            _ => panic!("Expected a Text node"),
+        }
+        assert_eq!(root.render_commonmark(), "*text*\n");
+    }
+
+    #[test]
+    fn test_iter_with_parent() {
+        let root = parse_document("# Title\n\nSome *text*\n");
+        let paragraph = root.at_path(&[1]).unwrap().unwrap();
+        let emph = root.at_path(&[1, 1]).unwrap().unwrap();
+
+        let parents: Vec<(u32, Option<u32>, IterEventType)> = root
+            .iter_with_parent()
+            .map(|(node, parent, event)| (node.get_id(), parent.map(|p| p.get_id()), event))
+            .collect();
+
+        let text = root.at_path(&[1, 0]).unwrap().unwrap();
+        assert!(parents.contains(&(
+            text.get_id(),
+            Some(paragraph.get_id()),
+            IterEventType::Enter
+        )));
+        let emphasized = emph.first_child().unwrap().unwrap();
+        assert!(parents.contains(&(
+            emphasized.get_id(),
+            Some(emph.get_id()),
+            IterEventType::Enter
+        )));
+        assert!(parents.contains(&(emph.get_id(), Some(paragraph.get_id()), IterEventType::Exit)));
+        assert_eq!(parents[0], (root.get_id(), None, IterEventType::Enter));
+        assert_eq!(
+            parents.last().unwrap(),
+            &(root.get_id(), None, IterEventType::Exit)
+        );
+    }
+
+    #[test]
+    fn test_blocks_with_text() {
+        let root = parse_document("# A *great* title\n\nFirst\nline\n\n> Quoted\n\n---\n");
+        let blocks: Vec<(NodeType, String)> = root
+            .blocks_with_text()
+            .unwrap()
+            .into_iter()
+            .map(|(block, text)| (block.get_cmark_type().unwrap(), text))
+            .collect();
+
+        assert_eq!(
+            blocks,
+            vec![
+                (NodeType::CMarkNodeHeading, "A great title".to_string()),
+                (NodeType::CMarkNodeParagraph, "First line".to_string()),
+                (NodeType::CMarkNodeBlockQuote, "Quoted".to_string()),
+                (NodeType::CMarkNodeParagraph, "Quoted".to_string()),
+                (NodeType::CMarkNodeThematicBreak, String::new()),
+            ]
+        );
+        for (block, text) in root.blocks_with_text().unwrap() {
+            assert_eq!(block.inner_text().unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_merge_adjacent_lists() {
+        let mut root = parse_document("* one\n* two\n\n- three\n\n+ four\n\n1. five\n");
+
+        assert_eq!(root.merge_adjacent_lists().unwrap(), 2);
+        assert_eq!(
+            root.count_children_of_type(NodeType::CMarkNodeList)
+                .unwrap(),
+            2
+        );
+
+        let list = root.first_child().unwrap().unwrap();
+        let items = list.as_list().unwrap().items().unwrap();
+        let texts: Vec<String> = items
+            .iter()
+            .map(|item| item.inner_text().unwrap())
+            .collect();
+        assert_eq!(texts, vec!["one", "two", "three", "four"]);
+        assert!(list.as_list().unwrap().get_list_tight());
+        assert_eq!(root.merge_adjacent_lists().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let root = parse_document("# Heading\n\nParagraph *text*\n\n* item\n");
+        let types: Vec<NodeType> = root
+            .blocks()
+            .unwrap()
+            .iter()
+            .map(|block| block.get_cmark_type().unwrap())
+            .collect();
+
+        assert_eq!(
+            types,
+            vec![
+                NodeType::CMarkNodeHeading,
+                NodeType::CMarkNodeParagraph,
+                NodeType::CMarkNodeList,
+            ]
+        );
+        assert!(root
+            .at_path(&[1])
+            .unwrap()
+            .unwrap()
+            .blocks()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_collapse_redundant_emphasis() {
+        let mut root = parse_document("*one* **_two_** and *three* *four*\n");
+        let paragraph = root.first_child().unwrap().unwrap();
+        for path in &[[0, 0], [0, 6], [0, 6]] {
+            let mut node = root.at_path(path).unwrap().unwrap();
+            node.wrap_in(Node::from_type(node.get_cmark_type().unwrap()).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(paragraph.subtree_size(), 16);
+        assert_eq!(root.collapse_redundant_emphasis().unwrap(), 4);
+        assert_eq!(paragraph.subtree_size(), 12);
+        assert_eq!(
+            root.render_commonmark(),
+            "*one* **two** and *three* *four*\n"
+        );
+
+        let mut root = parse_document("*__five__* and **_six_ seven**\n");
+        assert_eq!(root.collapse_redundant_emphasis().unwrap(), 1);
+        assert_eq!(root.render_commonmark(), "**five** and ***six* seven**\n");
+    }
+
+    #[test]
+    fn test_promote_code() {
+        let mut root = parse_document("`cargo build`\n\nRun `cargo test`\n");
+
+        assert_eq!(root.promote_code().unwrap(), 1);
+        match root.first_child().unwrap().unwrap() {
+            Node::CodeBlock(block) => {
+                assert_eq!(block.get_content().unwrap(), "cargo build\n");
+            }
+            _ => panic!("Expected a CodeBlock node"),
+        }
+        assert_eq!(
+            root.last_child()
+                .unwrap()
+                .unwrap()
+                .get_cmark_type()
+                .unwrap(),
+            NodeType::CMarkNodeParagraph
+        );
+    }
+
+    #[test]
+    fn test_as_variant() {
+        let root = parse_document("# Title\n\nSome [link](url)\n");
+        let heading = root.first_child().unwrap().unwrap();
+        let paragraph = root.last_child().unwrap().unwrap();
+
+        assert_eq!(heading.as_heading().unwrap().get_level(), 1);
+        assert!(paragraph.as_heading().is_none());
+        assert!(heading.as_link().is_none());
+
+        let link = paragraph.last_child().unwrap().unwrap();
+        assert_eq!(link.as_link().unwrap().get_url().unwrap(), "url");
+        assert!(root.as_document().is_some());
+    }
+
+    #[test]
+    fn test_as_variant_mut() {
+        let root = parse_document("# Title\n");
+        let mut heading = root.first_child().unwrap().unwrap();
+
+        heading.as_heading_mut().unwrap().set_level(3).unwrap();
+        assert!(heading.as_text_mut().is_none());
+        assert_eq!(root.render_commonmark(), "### Title\n");
+    }
+
+    #[test]
+    fn test_is_descendant_of() {
+        let root = parse_document("* one\n* two\n");
+        let first = root.at_path(&[0, 0]).unwrap().unwrap();
+        let second = root.at_path(&[0, 1]).unwrap().unwrap();
+
+        assert!(second.is_descendant_of(&root).unwrap());
+        assert!(!second.is_descendant_of(&first).unwrap());
+        assert!(!second.is_descendant_of(&second).unwrap());
+        assert!(!root.is_descendant_of(&second).unwrap());
+    }
+
+    #[test]
+    fn test_is_sibling_of() {
+        let root = parse_document("* one\n* two\n\n- three\n");
+        let first = root.at_path(&[0, 0]).unwrap().unwrap();
+        let second = root.at_path(&[0, 1]).unwrap().unwrap();
+        let third = root.at_path(&[1, 0]).unwrap().unwrap();
+
+        assert!(first.is_sibling_of(&second).unwrap());
+        assert!(second.is_sibling_of(&first).unwrap());
+        assert!(!second.is_sibling_of(&third).unwrap());
+        assert!(!root.is_sibling_of(&root).unwrap());
+    }
+
+    #[test]
+    fn test_range_between() {
+        let root = parse_document("* one\n* two\n* three\n");
+        let list = root.first_child().unwrap().unwrap();
+        let items = list.children().unwrap();
+
+        let range = list.range_between(&items[1], &items[2]).unwrap();
+        assert_eq!(range, &items[1..]);
+        assert_eq!(
+            list.range_between(&items[0], &items[0]).unwrap(),
+            &items[..1]
+        );
+        match list.range_between(&items[2], &items[1]) {
+            Err(DoogieError::InvalidArgument(_)) => (),
+            _ => panic!("Expected an InvalidArgument error"),
+        }
+        match root.range_between(&items[0], &items[1]) {
+            Err(DoogieError::InvalidArgument(_)) => (),
+            _ => panic!("Expected an InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let root = parse_document("* one\n* two\n* three\n* four\n");
+        let mut list = root.first_child().unwrap().unwrap();
+        let mut start = root.at_path(&[0, 1]).unwrap().unwrap();
+        let mut end = root.at_path(&[0, 2]).unwrap().unwrap();
+
+        assert_eq!(list.delete_range(&mut start, &mut end).unwrap(), 2);
+        assert_eq!(root.render_commonmark(), "- one\n- four\n");
+        assert!(start.parent().unwrap().is_none());
+        assert!(list.delete_range(&mut start, &mut end).is_err());
+    }
+
+    #[test]
+    fn test_to_hard_break() {
+        let root = parse_document("First\nsecond\n");
+        let mut soft = root.at_path(&[0, 1]).unwrap().unwrap();
+
+        let mut hard = soft.to_hard_break().unwrap();
+        assert_eq!(hard.get_cmark_type().unwrap(), NodeType::CMarkNodeLinebreak);
+        let rendered = root.render_commonmark();
+        assert!(rendered.starts_with("First\\\n") || rendered.starts_with("First  \n"));
+        assert!(parse_document(&rendered)
+            .contains_type(NodeType::CMarkNodeLinebreak)
+            .unwrap());
+        match soft.to_hard_break() {
+            Err(DoogieError::NoParent) => (),
+            _ => panic!("Expected a NoParent error"),
+        }
+
+        hard.to_soft_break().unwrap();
+        assert_eq!(root.render_commonmark(), "First\nsecond\n");
+        match root.at_path(&[0, 0]).unwrap().unwrap().to_soft_break() {
+            Err(DoogieError::WrongNodeType { .. }) => (),
+            _ => panic!("Expected a WrongNodeType error"),
+        }
+    }
+
+    #[test]
+    fn test_loose_items() {
+        let root = parse_document("* one\n* two\n\n* three\n\n  more\n* four\n\n> * five\n");
+        let loose = root.first_child().unwrap().unwrap();
+        let tight = root.at_path(&[1, 0]).unwrap().unwrap();
+
+        assert!(!loose.as_list().unwrap().get_list_tight());
+        assert_eq!(loose.as_list().unwrap().loose_item_count().unwrap(), 2);
+        assert_eq!(
+            loose.as_list().unwrap().loose_items().unwrap(),
+            &loose.children().unwrap()[1..3]
+        );
+        assert!(tight.as_list().unwrap().get_list_tight());
+        assert_eq!(tight.as_list().unwrap().loose_item_count().unwrap(), 0);
+
+        match List::bullet(true).unwrap().loose_items() {
+            Err(DoogieError::SourceUnavailable) => (),
+            _ => panic!("Expected a SourceUnavailable error"),
+        }
+    }
+
+    #[test]
+    fn test_is_empty_document() {
+        let mut blank = parse_document("Text\n");
+        blank.map_text(|_| "  ".to_string()).unwrap();
+
+        assert!(parse_document("").is_empty_document().unwrap());
+        assert!(parse_document(" \n\n\t\n").is_empty_document().unwrap());
+        assert!(blank.is_empty_document().unwrap());
+        assert!(!parse_document("# x").is_empty_document().unwrap());
+        assert!(!parse_document("---\n").is_empty_document().unwrap());
+        match blank.first_child().unwrap().unwrap().is_empty_document() {
+            Err(DoogieError::WrongNodeType { .. }) => (),
+            _ => panic!("Expected a WrongNodeType error"),
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        let document = parse_document("> Some *text*\n");
+        let text = document.at_path(&[0, 0, 1, 0]).unwrap().unwrap();
+
+        let root = text.root().unwrap();
+        assert_eq!(root.get_cmark_type().unwrap(), NodeType::CMarkNodeDocument);
+        assert_eq!(root.get_id(), document.get_id());
+        assert_eq!(document.root().unwrap().get_id(), document.get_id());
+    }
+
+    #[test]
+    fn test_has_children() {
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        assert!(!paragraph.has_children());
+
+        let mut text = Node::from_type(NodeType::CMarkNodeText).unwrap();
+        paragraph.append_child(&mut text).unwrap();
+        assert!(paragraph.has_children());
+        assert!(!text.has_children());
+    }
+
+    #[test]
+    fn test_subtree_size() {
+        let root = parse_document("# Title\n\nSome *text*\n");
+
+        assert_eq!(root.subtree_size(), 7);
+        assert_eq!(root.at_path(&[1, 1]).unwrap().unwrap().subtree_size(), 2);
+    }
+
+    #[test]
+    fn test_height() {
+        let root = parse_document("> > Nested *quote*\n");
+
+        assert_eq!(root.height(), 5);
+        assert_eq!(root.at_path(&[0, 0]).unwrap().unwrap().height(), 3);
+        assert_eq!(root.at_path(&[0, 0, 0, 0]).unwrap().unwrap().height(), 0);
+    }
+
+    #[test]
+    fn test_max_depth_exceeded() {
+        let root = parse_document(&format!("{} deep\n", ">".repeat(100)));
+        let height = root.height();
+
+        assert_eq!(height, 102);
+        assert!(root.max_depth_exceeded(10).unwrap());
+        assert!(root.max_depth_exceeded(height - 1).unwrap());
+        assert!(!root.max_depth_exceeded(height).unwrap());
+        assert!(!parse_document("").max_depth_exceeded(0).unwrap());
+    }
+
+    #[test]
+    fn test_item_is_empty() {
+        let root = parse_document("* \n* text\n* `code`\n* blank\n");
+        let items = match root.first_child().unwrap().unwrap() {
+            Node::List(list) => list.items().unwrap(),
+            _ => panic!("Expected a List node"),
+        };
+        let mut blank = items[3].at_path(&[0, 0]).unwrap().unwrap();
+        blank
+            .as_text_mut()
+            .unwrap()
+            .set_content(&" ".to_string())
+            .unwrap();
+
+        let empty: Vec<bool> = items
+            .iter()
+            .map(|item| match *item {
+                Node::Item(ref item) => item.is_empty().unwrap(),
+                _ => panic!("Expected an Item node"),
+            })
+            .collect();
+
+        assert_eq!(empty, vec![true, false, false, true]);
+        assert!(Item::new().is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_list_ordered() {
+        let list = List::ordered(3, true).unwrap();
+        assert!(list.get_list_tight());
+        assert_eq!(list.get_list_start(), 3);
+
+        let mut list = Node::List(list);
+        for content in &["first", "second"] {
+            let mut item = Node::from_type(NodeType::CMarkNodeItem).unwrap();
+            item.append_paragraph_text(content).unwrap();
+            list.append_child(&mut item).unwrap();
+        }
+        let rendered = list.render_commonmark();
+        let markers: Vec<&str> = rendered
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(markers, vec!["3.", "4."]);
+
+        let bullet = List::bullet(false).unwrap();
+        assert!(!bullet.get_list_tight());
+        assert!(bullet.get_list_type().unwrap() == ListType::CMarkBulletList);
+    }
+
+    #[test]
+    fn test_count_children_of_type() {
+        let root = parse_document("* one\n* two\n\n  > quote\n* three\n");
+        let list = root.first_child().unwrap().unwrap();
+
+        let items = match list {
+            Node::List(ref list) => list.items().unwrap(),
+            _ => panic!("Expected a List node"),
+        };
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            list.count_children_of_type(NodeType::CMarkNodeItem)
+                .unwrap(),
+            items.len()
+        );
+        assert_eq!(
+            root.count_children_of_type(NodeType::CMarkNodeBlockQuote)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            items[1]
+                .count_children_of_type(NodeType::CMarkNodeParagraph)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_contains_type() {
+        let plain = parse_document("Just *text*\n");
+        let linked = parse_document("> A [link](url)\n");
+
+        assert!(!plain.contains_type(NodeType::CMarkNodeLink).unwrap());
+        assert!(linked.contains_type(NodeType::CMarkNodeLink).unwrap());
+        assert!(plain.contains_type(NodeType::CMarkNodeDocument).unwrap());
+    }
+
+    #[test]
+    fn test_text_in_type() {
+        let root = parse_document("> First *quote*\n\nOutside\n\n> Second\n> quote\n");
+
+        assert_eq!(
+            root.text_in_type(NodeType::CMarkNodeBlockQuote).unwrap(),
+            vec!["First quote", "Second quote"]
+        );
+        assert!(root
+            .text_in_type(NodeType::CMarkNodeLink)
+            .unwrap()
+            .is_empty());
     }
-}
 
-/// Manages the memory resources of `Node` instances.
-#[derive(Debug)]
-struct ResourceManager {
-    roots: RefCell<Vec<*mut CMarkNodePtr>>,
-}
+    #[test]
+    fn test_shift_headings() {
+        let mut root = parse_document("# One\n\n## Two\n\n###### Six\n");
 
-impl Drop for ResourceManager {
-    fn drop(&mut self) {
-        let roots = self.roots.borrow();
-        for pointer in roots.iter() {
-            unsafe {
-                cmark_node_free(*pointer);
-            }
-        }
+        root.shift_headings(1).unwrap();
+        assert_eq!(
+            root.render_commonmark(),
+            "## One\n\n### Two\n\n###### Six\n"
+        );
+
+        root.shift_headings(-4).unwrap();
+        assert_eq!(root.render_commonmark(), "# One\n\n# Two\n\n## Six\n");
     }
-}
 
-impl ResourceManager {
-    /// Construct a new ResourceManager instance.
-    pub fn new() -> ResourceManager {
-        ResourceManager {
-            roots: RefCell::new(Vec::new()),
-        }
+    #[test]
+    fn test_retain() {
+        let mut root = parse_document("One\n\n***\n\n> Two\n>\n> ---\n\n___\n");
+
+        let removed = root
+            .retain(|node| match *node {
+                Node::ThematicBreak(_) => false,
+                _ => true,
+            })
+            .unwrap();
+
+        assert_eq!(removed, 3);
+        assert!(root
+            .iter()
+            .all(|(node, _)| node.get_cmark_type().unwrap() != NodeType::CMarkNodeThematicBreak));
+        assert_eq!(root.render_commonmark(), "One\n\n> Two\n");
     }
 
-    /// Tracks the given pointer as a root Node of some tree or subtree
-    pub fn track_root(&self, pointer: &*mut CMarkNodePtr) {
-        let mut roots = self.roots.borrow_mut();
-        if !roots.contains(&pointer) {
-            roots.push(pointer.clone());
-        }
+    #[test]
+    fn test_prune_empty() {
+        let mut root = parse_document("*two*\n\nOne\n\n![](image.png)\n");
+        let emph = root.at_path(&[0, 0]).unwrap().unwrap();
+        emph.first_child().unwrap().unwrap().unlink();
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        let mut emph = Node::from_type(NodeType::CMarkNodeEmph).unwrap();
+        let mut text = Node::from_type(NodeType::CMarkNodeText).unwrap();
+        emph.append_child(&mut text).unwrap();
+        paragraph.append_child(&mut emph).unwrap();
+        root.append_child(&mut paragraph).unwrap();
+
+        assert_eq!(root.prune_empty().unwrap(), 5);
+        assert_eq!(root.render_commonmark(), "One\n\n![](image.png)\n");
     }
 
-    /// Removes the tracking for a given pointer
-    pub fn untrack_root(&self, pointer: &*mut CMarkNodePtr) {
-        let mut roots = self.roots.borrow_mut();
-        roots.remove_item(pointer);
+    #[test]
+    fn test_render_inner_commonmark() {
+        let root = parse_document("> One\n>\n> Two *three*\n");
+        let quote = root.first_child().unwrap().unwrap();
+        let paragraph = quote.last_child().unwrap().unwrap();
+
+        assert_eq!(
+            quote.render_inner_commonmark().unwrap(),
+            "One\n\nTwo *three*\n"
+        );
+        assert_eq!(paragraph.render_inner_commonmark().unwrap(), "Two *three*");
+
+        let root = parse_document("First *line*\nsecond\\\nthird\n");
+        let paragraph = root.first_child().unwrap().unwrap();
+        assert_eq!(
+            paragraph.render_inner_commonmark().unwrap(),
+            "First *line*\nsecond\\\nthird"
+        );
     }
 
-    #[cfg(test)]
-    /// Determines if the given pointer is currently being tracked
-    pub fn is_tracking(&self, pointer: &*mut CMarkNodePtr) -> bool {
-        let roots = self.roots.borrow();
-        roots.contains(pointer)
+    #[test]
+    fn test_renders_same() {
+        let mut root = Node::from_type(NodeType::CMarkNodeDocument).unwrap();
+        root.manager().track_root(&root.pointer());
+        let mut paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        for content in &["Hello ", "world"] {
+            let mut text = Text::new();
+            text.set_content(&content.to_string()).unwrap();
+            paragraph.append_child(&mut Node::Text(text)).unwrap();
+        }
+        root.append_child(&mut paragraph).unwrap();
+
+        assert!(root.renders_same(&parse_document("Hello world\n")));
+        assert!(!root.renders_same(&parse_document("Hello *world*\n")));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{
-        cmark_node_new, parse_document, CMarkNodePtr, CodeBlock, IterEventType, Node, NodeResource,
-        NodeType, Text,
-    };
-    use constants::*;
-    use proptest::prelude::*;
-    use try_from::TryFrom;
+    #[test]
+    fn test_render_commonmark_normalized() {
+        let root = parse_document("Title\n=====\n\n\n\n+ one\n+ two\n\n\n* three\n");
+        let normalized = root.render_commonmark_normalized();
 
-    /// Returns some arbitrary alphanumeric textual content
-    fn arb_content(max_words: usize) -> BoxedStrategy<String> {
-        prop::collection::vec("[[:alnum:]]{1,45}", 1..max_words)
-            .prop_map(|v| v.join(" "))
-            .boxed()
+        assert!(normalized.starts_with("# Title\n\n"));
+        assert!(!normalized.contains("\n\n\n"));
+        assert_eq!(
+            parse_document(&normalized).render_commonmark_normalized(),
+            normalized
+        );
     }
 
     #[test]
-    fn test_parse_document() {
-        let body = "\
-        # My New Document
-        ";
-        let node = parse_document(body);
+    fn test_render_commonmark_setext() {
+        let root = parse_document("# One\n\n## Two\n\n### Three\n\n```sh\n# code\n```\n");
 
-        match node {
-            Node::Document(_) => (),
-            _ => panic!("Did not get a Document Node after parsing."),
+        let rendered = root.render_commonmark_setext();
+
+        assert!(rendered.starts_with("One\n===\n\nTwo\n---\n\n### Three\n\n```"));
+        assert!(rendered.ends_with("\n# code\n```\n"));
+
+        for atx in &["## - x\n", "# 1. a\n", "# > q\n", "## ---\n"] {
+            let root = parse_document(atx);
+            let rendered = root.render_commonmark_setext();
+            assert_eq!(
+                parse_document(&rendered).render_commonmark(),
+                root.render_commonmark()
+            );
         }
+
+        let html = "<div>\n# not a heading\n</div>\n";
+        assert_eq!(parse_document(html).render_commonmark_setext(), html);
     }
 
     #[test]
-    fn test_equality() {
-        let body = "\
-        # My New Document
-        ";
-        let node = parse_document(body);
-        let other = node.itself().unwrap();
+    fn test_normalize_text() {
+        let mut root = parse_document("a    b\t\tc `x    y` *d*\n");
 
-        assert_eq!(node, other);
+        assert_eq!(root.normalize_text().unwrap(), 1);
+        assert_eq!(root.render_commonmark(), "a b c `x    y` *d*\n");
     }
 
     #[test]
-    fn test_inequality() {
-        let body = "\
-        # My New Document
-        ";
-        let node = parse_document(body);
-        let other = node.first_child()
-            .unwrap()
-            .expect("Root should have a child");
+    fn test_strip_html() {
+        let mut root = parse_document("<script>alert(1)</script>\n\nSafe <b>text</b>\n");
 
-        assert_ne!(node, other);
+        assert_eq!(root.strip_html().unwrap(), 3);
+        assert_eq!(root.render_commonmark(), "Safe text\n");
     }
 
     #[test]
-    fn test_root_node_gets_tracked() {
-        let body = "\
-        # My New Document
-        ";
-        let manager;
-        let pointer;
-        {
-            let node = parse_document(body);
-            manager = node.manager();
-            pointer = node.pointer();
+    fn test_insert_siblings() {
+        let root = parse_document("* one\n* three\n");
+        let mut first = root.at_path(&[0, 0]).unwrap().unwrap();
+        let mut last = root.at_path(&[0, 1]).unwrap().unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        root.observe_mutations(move |event| recorded.borrow_mut().push(event));
+
+        let mut two = Node::Item(Item::new());
+        two.append_paragraph_text("two").unwrap();
+        first.insert_after(&mut two).unwrap();
+        assert_eq!(first.next_sibling().unwrap(), Some(two.itself().unwrap()));
+        assert_eq!(last.prev_sibling().unwrap(), Some(two.itself().unwrap()));
+
+        let mut zero = Node::Item(Item::new());
+        zero.append_paragraph_text("zero").unwrap();
+        first.insert_before(&mut zero).unwrap();
+        assert_eq!(first.prev_sibling().unwrap(), Some(zero.itself().unwrap()));
+
+        let mut four = Node::Item(Item::new());
+        four.append_paragraph_text("four").unwrap();
+        last.insert_after(&mut four).unwrap();
+        assert_eq!(
+            root.render_commonmark(),
+            "- zero\n- one\n- two\n- three\n- four\n"
+        );
+        assert_eq!(
+            events.borrow()[0],
+            MutationEvent::InsertAfter {
+                node: two.event_id(),
+                after: first.event_id(),
+            }
+        );
+
+        let mut paragraph = Node::Paragraph(Paragraph::new());
+        match first.insert_after(&mut paragraph) {
+            Err(DoogieError::InvalidChild { parent, child }) => {
+                assert_eq!(parent, NodeType::CMarkNodeList);
+                assert_eq!(child, NodeType::CMarkNodeParagraph);
+            }
+            _ => panic!("Expected an InvalidChild error"),
         }
-        assert!(manager.roots.borrow().contains(&pointer));
+        match root.itself().unwrap().insert_before(&mut paragraph) {
+            Err(DoogieError::NoParent) => (),
+            _ => panic!("Expected a NoParent error"),
+        }
+        assert_eq!(
+            root.first_child()
+                .unwrap()
+                .unwrap()
+                .children()
+                .unwrap()
+                .len(),
+            5
+        );
     }
 
     #[test]
-    fn test_iterator_hits_all_items() {
-        let body = "* Item 1\n* Item 2\n* Item 3";
-        let root = parse_document(body);
-        let mut node_contents: Vec<String> = Vec::new();
-        let mut item_count = 0;
+    fn test_prepend_child() {
+        let mut root = parse_document("Existing content\n");
+        let mut banner = Node::Paragraph(Paragraph::new());
+        banner
+            .append_child(&mut Node::new_text("Banner").unwrap())
+            .unwrap();
+
+        root.prepend_child(&mut banner).unwrap();
+        assert_eq!(root.render_commonmark(), "Banner\n\nExisting content\n");
+        assert_eq!(banner.next_sibling().unwrap(), root.last_child().unwrap());
+
+        let mut item = Node::Item(Item::new());
+        match root.prepend_child(&mut item) {
+            Err(DoogieError::InvalidChild { parent, child }) => {
+                assert_eq!(parent, NodeType::CMarkNodeDocument);
+                assert_eq!(child, NodeType::CMarkNodeItem);
+            }
+            _ => panic!("Expected an InvalidChild error"),
+        }
+    }
 
-        for item in root.iter() {
-            match item {
-                (Node::Item(_), IterEventType::Enter) => item_count += 1,
-                (Node::Text(ref text), IterEventType::Enter) => {
-                    node_contents.push(text.get_content().unwrap())
-                }
-                _ => (),
+    #[test]
+    fn test_insert_ancestor() {
+        let root = parse_document("> > nested\n");
+        let mut outer = root.first_child().unwrap().unwrap();
+        let mut inner = outer.first_child().unwrap().unwrap();
+        let mut paragraph = inner.first_child().unwrap().unwrap();
+
+        let mut same_outer = outer.itself().unwrap();
+        let mut same_inner = inner.itself().unwrap();
+        let mut same_paragraph = paragraph.itself().unwrap();
+
+        let results = vec![
+            paragraph.insert_after(&mut same_outer),
+            paragraph.insert_before(&mut same_inner),
+            paragraph.insert_after(&mut same_paragraph),
+            inner.prepend_child(&mut same_outer),
+            outer.prepend_child(&mut same_outer),
+        ];
+        for result in results {
+            match result {
+                Err(DoogieError::InvalidArgument(_)) => (),
+                _ => panic!("Expected an InvalidArgument error"),
             }
         }
+        assert_eq!(root.render_commonmark(), "> > nested\n");
+        assert_eq!(inner.parent().unwrap(), Some(outer.itself().unwrap()));
+        assert_eq!(outer.parent().unwrap(), Some(root.itself().unwrap()));
+    }
 
-        assert_eq!(item_count, 3);
-        assert!(node_contents.contains(&String::from("Item 1")));
-        assert!(node_contents.contains(&String::from("Item 2")));
-        assert!(node_contents.contains(&String::from("Item 3")));
+    #[test]
+    fn test_set_children() {
+        let root = parse_document("* one\n* two\n* three\n");
+        let mut list = root.first_child().unwrap().unwrap();
+        let old = list.children().unwrap();
+        let mut new_item = Node::Item(Item::new());
+        new_item.append_paragraph_text("four").unwrap();
+
+        list.set_children(vec![old.into_iter().nth(2).unwrap(), new_item])
+            .unwrap();
+        assert_eq!(root.render_commonmark(), "- three\n- four\n");
+
+        let paragraph = Node::Paragraph(Paragraph::new());
+        match list.set_children(vec![paragraph]) {
+            Err(DoogieError::InvalidChild { parent, child }) => {
+                assert_eq!(parent, NodeType::CMarkNodeList);
+                assert_eq!(child, NodeType::CMarkNodeParagraph);
+            }
+            _ => panic!("Expected an InvalidChild error"),
+        }
+        assert_eq!(root.render_commonmark(), "- three\n- four\n");
+
+        let root = parse_document("> > nested\n");
+        let mut outer = root.first_child().unwrap().unwrap();
+        let mut inner = outer.first_child().unwrap().unwrap();
+        for invalid in vec![outer.itself().unwrap(), inner.itself().unwrap()] {
+            match inner.set_children(vec![invalid]) {
+                Err(DoogieError::InvalidArgument(_)) => (),
+                _ => panic!("Expected an InvalidArgument error"),
+            }
+        }
+        let freed = parse_document("> freed\n");
+        let stale = freed.first_child().unwrap().unwrap();
+        freed.close().unwrap();
+        match outer.set_children(vec![stale]) {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("Expected a ResourceUnavailable error"),
+        }
+        assert_eq!(root.render_commonmark(), "> > nested\n");
     }
 
     #[test]
-    fn test_parent_child_traversal() {
-        let body = "* Item 1\n* Item 2\n* Item 3";
-        let root = parse_document(body);
-        let child = root.first_child()
-            .unwrap()
-            .expect("Root should have had child");
+    fn test_content_hash() {
+        let content = "# Title\n\n* [link](url \"title\")\n\n```rust\ncode\n```\n";
+        let root = parse_document(content);
+        let mut changed = parse_document(content);
+
         assert_eq!(
-            root,
-            child
-                .parent()
+            root.content_hash().unwrap(),
+            parse_document(content).content_hash().unwrap()
+        );
+        assert_eq!(
+            root.content_hash().unwrap(),
+            parse_document(&root.render_commonmark())
+                .content_hash()
                 .unwrap()
-                .expect("Child should have had a parent")
         );
+
+        changed.map_text(|text| text.to_uppercase()).unwrap();
+        assert_ne!(
+            root.content_hash().unwrap(),
+            changed.content_hash().unwrap()
+        );
+        assert_ne!(
+            root.content_hash().unwrap(),
+            root.first_child().unwrap().unwrap().content_hash().unwrap()
+        );
+
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let freed = root.itself().unwrap();
+        root.close().unwrap();
+        match freed.content_hash() {
+            Err(DoogieError::ResourceUnavailable) => (),
+            _ => panic!("Hashing a freed node should have failed"),
+        }
     }
 
     #[test]
-    fn test_sibling_traversal() {
-        let body = "* Item 1\n* Item 2\n* Item 3";
-        let root = parse_document(body);
-        let list = root.first_child()
-            .unwrap()
-            .expect("Root should have had list");
-        let first_item = list.first_child()
-            .unwrap()
-            .expect("List should have had item");
-        let next_item = first_item
-            .next_sibling()
+    fn test_flatten_links() {
+        let mut root = parse_document("[text](url)\n");
+
+        assert_eq!(root.flatten_links().unwrap(), 1);
+        assert_eq!(root.render_commonmark(), "text\n");
+    }
+
+    #[test]
+    fn test_autolink_text() {
+        let mut root =
+            parse_document("See https://example.com/a_b, or (http://x.org). [https://a.com](u)\n");
+
+        assert_eq!(root.autolink_text().unwrap(), 2);
+        let paragraph = root.first_child().unwrap().unwrap();
+        let children: Vec<(NodeType, String)> = paragraph
+            .children()
             .unwrap()
-            .expect("First item should have had next sibling");
+            .into_iter()
+            .map(|child| (child.get_cmark_type().unwrap(), child.inner_text().unwrap()))
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                (NodeType::CMarkNodeText, "See ".to_string()),
+                (
+                    NodeType::CMarkNodeLink,
+                    "https://example.com/a_b".to_string()
+                ),
+                (NodeType::CMarkNodeText, ", or (".to_string()),
+                (NodeType::CMarkNodeLink, "http://x.org".to_string()),
+                (NodeType::CMarkNodeText, "). ".to_string()),
+                (NodeType::CMarkNodeLink, "https://a.com".to_string()),
+            ]
+        );
+        match paragraph.at_path(&[1]).unwrap().unwrap() {
+            Node::Link(ref link) => assert_eq!(link.get_url().unwrap(), "https://example.com/a_b"),
+            _ => panic!("Expected a Link node"),
+        }
+        assert_eq!(root.autolink_text().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_escape_commonmark() {
+        let text = "*not bold* [no](link) `code`\n# no heading\n1. no list";
+        let root = parse_document(&escape_commonmark(text));
 
+        assert_eq!(root.inner_text().unwrap(), text.replace('\n', " ") + "\n");
         assert_eq!(
-            first_item,
-            next_item
-                .prev_sibling()
+            root.first_child()
                 .unwrap()
-                .expect("Next item should have had prev item")
+                .unwrap()
+                .get_cmark_type()
+                .unwrap(),
+            NodeType::CMarkNodeParagraph
         );
+        assert!(root.iter().all(|(node, _)| match node {
+            Node::Emph(_) | Node::Link(_) | Node::Code(_) => false,
+            _ => true,
+        }));
     }
 
-    #[test]
-    fn parse_and_render() {
-        let content = "# Testing";
-        let root = parse_document(content);
+    proptest! {
+        #[test]
+        fn test_escape_commonmark_round_trip(ref text in "[ -~\t\n\r]{0,60}") {
+            let root = parse_document(&escape_commonmark(text));
+            let mut parsed = String::new();
+            for (node, event) in root.iter() {
+                match (&node, event) {
+                    (&Node::Text(ref content), IterEventType::Enter) => {
+                        parsed.push_str(&content.get_content().unwrap())
+                    }
+                    (&Node::SoftBreak(_), IterEventType::Enter) => parsed.push('\n'),
+                    _ => {}
+                }
+            }
 
-        assert_eq!(content, root.render_commonmark().trim());
+            assert_eq!(&parsed, text);
+            assert!(root.first_child().unwrap().map_or(true, |paragraph| {
+                paragraph.next_sibling().unwrap().is_none()
+            }));
+        }
     }
 
     #[test]
-    fn test_from_raw() {
-        let node_pointer: *mut CMarkNodePtr;
-        unsafe {
-            node_pointer = cmark_node_new(NodeType::CMarkNodeParagraph as u32);
-        }
+    fn test_can_insert_sibling() {
+        let root = parse_document("* item\n");
+        let item = root.at_path(&[0, 0]).unwrap().unwrap();
+        let paragraph = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+        let other_item = Node::from_type(NodeType::CMarkNodeItem).unwrap();
+
+        assert!(item.can_prepend_child(&paragraph).unwrap());
+        assert!(!item.can_insert_sibling(&paragraph).unwrap());
+        assert!(item.can_insert_sibling(&other_item).unwrap());
+        assert!(!root.can_insert_sibling(&paragraph).unwrap());
+    }
 
-        let node = Node::from_raw(node_pointer).unwrap();
+    #[test]
+    fn test_allowed_child_types() {
+        let list = Node::from_type(NodeType::CMarkNodeList).unwrap();
+        let text = Node::from_type(NodeType::CMarkNodeText).unwrap();
+
+        assert_eq!(list.allowed_child_types(), &[NodeType::CMarkNodeItem]);
+        assert!(text.allowed_child_types().is_empty());
+
+        for node_type in NodeType::all() {
+            let node = Node::from_type(node_type.clone()).unwrap();
+            for child_type in NodeType::all() {
+                let child = Node::from_type(child_type.clone()).unwrap();
+                assert_eq!(
+                    node.allowed_child_types()
+                        .contains(&child.get_cmark_type().unwrap()),
+                    node.can_append_child(&child).unwrap()
+                );
+            }
+        }
+    }
 
-        match node {
-            Node::Paragraph(_) => (),
-            _ => panic!("Node should have been a paragraph"),
+    #[test]
+    fn test_node_type_from_u32() {
+        assert_eq!(NodeType::from_u32(1).unwrap(), NodeType::CMarkNodeDocument);
+        assert_eq!(NodeType::from_u32(20).unwrap(), NodeType::CMarkNodeImage);
+        assert_eq!(u32::from(NodeType::from_u32(9).unwrap()), 9);
+
+        match NodeType::from_u32(21) {
+            Err(DoogieError::BadEnum(21)) => (),
+            _ => panic!("21 is not a libcmark node type"),
         }
     }
 
     #[test]
-    fn test_unlink() {
-        let body = "* Item 1\n* Item 2\n* Item 3";
-        let root = parse_document(body);
-        let mut first_item = root.first_child()
-            .unwrap()
-            .expect("Root should have first child")
-            .first_child()
-            .unwrap()
-            .expect("List should have first item");
-        let manager = first_item.manager();
+    fn test_node_type_all() {
+        let all = NodeType::all();
 
-        first_item.unlink();
+        assert_eq!(all.len(), 20);
+        for (i, node_type) in all.iter().enumerate() {
+            assert_eq!(u32::from(node_type.clone()), i as u32 + 1);
+            assert_eq!(NodeType::from_u32(i as u32 + 1).unwrap(), *node_type);
+        }
+    }
 
-        assert!(manager.roots.borrow().contains(&first_item.pointer()));
-        for (node, _) in root.iter() {
-            if let Node::Text(node) = node {
-                assert!(!node.get_content().unwrap().contains("Item 1"));
-            }
+    #[test]
+    fn test_node_type_is_container() {
+        assert!(NodeType::CMarkNodeDocument.is_container());
+        assert!(!NodeType::CMarkNodeThematicBreak.is_container());
+
+        for node_type in NodeType::all() {
+            let node = Node::from_type(node_type.clone()).unwrap();
+            assert_eq!(
+                node_type.is_container(),
+                !node.allowed_child_types().is_empty()
+            );
         }
     }
 
     #[test]
-    fn test_append_child() {
-        let mut root_node = Node::from_type(NodeType::CMarkNodeDocument).unwrap();
-        let mut child_node = Node::from_type(NodeType::CMarkNodeParagraph).unwrap();
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("&amp; &#169; &#xA9; &#0; &bogus; & x &#xZZ; &lt"),
+            "& \u{a9} \u{a9} \u{fffd} &bogus; & x &#xZZ; &lt"
+        );
 
-        root_node.append_child(&mut child_node).unwrap();
+        let mut root = parse_document("Copyright *dash*\n");
+        root.map_text(|text| match text {
+            "dash" => "&#8212;".to_string(),
+            _ => format!("{}&copy; ", text),
+        })
+        .unwrap();
+        assert_eq!(root.decode_text_entities().unwrap(), 2);
+        assert_eq!(root.inner_text().unwrap(), "Copyright \u{a9} \u{2014}\n");
+    }
+
+    #[test]
+    fn test_concat_code_blocks() {
+        let root = parse_document(
+            "```rust\nfn main() {}\n```\n\n```sh\ncargo run\n```\n\n```rust\nfn other() {}\n```\n",
+        );
 
-        assert!(!root_node.manager().is_tracking(&child_node.pointer()));
         assert_eq!(
-            root_node
-                .first_child()
-                .unwrap()
-                .expect("Root should have child"),
-            child_node
+            root.concat_code_blocks(Some("rust")).unwrap(),
+            "fn main() {}\nfn other() {}\n"
         );
+        assert_eq!(
+            root.concat_code_blocks(None).unwrap(),
+            "fn main() {}\ncargo run\nfn other() {}\n"
+        );
+        assert_eq!(root.concat_code_blocks(Some("python")).unwrap(), "");
     }
 
     #[test]
     fn test_document_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeDocument;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1386,9 +7518,8 @@ mod tests {
 
     #[test]
     fn test_block_quote_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeBlockQuote;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1420,9 +7551,8 @@ mod tests {
 
     #[test]
     fn test_list_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeList;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1454,9 +7584,8 @@ mod tests {
 
     #[test]
     fn test_item_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeItem;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1488,9 +7617,8 @@ mod tests {
 
     #[test]
     fn test_code_block_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeCodeBlock;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1522,9 +7650,8 @@ mod tests {
 
     #[test]
     fn test_html_block_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeHtmlBlock;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1556,9 +7683,8 @@ mod tests {
 
     #[test]
     fn test_custom_block_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeCustomBlock;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1590,9 +7716,8 @@ mod tests {
 
     #[test]
     fn test_paragraph_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeParagraph;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1624,9 +7749,8 @@ mod tests {
 
     #[test]
     fn test_heading_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeHeading;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1658,9 +7782,8 @@ mod tests {
 
     #[test]
     fn test_thematic_break_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeThematicBreak;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1692,9 +7815,8 @@ mod tests {
 
     #[test]
     fn test_text_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeText;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1726,9 +7848,8 @@ mod tests {
 
     #[test]
     fn test_soft_break_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeSoftbreak;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1760,9 +7881,8 @@ mod tests {
 
     #[test]
     fn test_line_break_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeLinebreak;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1794,9 +7914,8 @@ mod tests {
 
     #[test]
     fn test_code_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeCode;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1828,9 +7947,8 @@ mod tests {
 
     #[test]
     fn test_inline_html_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeHtmlInline;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1862,9 +7980,8 @@ mod tests {
 
     #[test]
     fn test_custom_inline_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeCustomInline;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1896,9 +8013,8 @@ mod tests {
 
     #[test]
     fn test_emph_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeEmph;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1930,9 +8046,8 @@ mod tests {
 
     #[test]
     fn test_strong_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeStrong;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1964,9 +8079,8 @@ mod tests {
 
     #[test]
     fn test_link_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeLink;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -1998,9 +8112,8 @@ mod tests {
 
     #[test]
     fn test_image_children() {
-        for i in 1..21 {
+        for other_type in NodeType::all().iter().cloned() {
             let node_type = NodeType::CMarkNodeImage;
-            let other_type = NodeType::try_from(i).unwrap();
             let mut node = Node::from_type(node_type).unwrap();
             let mut child = Node::from_type(other_type.clone()).unwrap();
             match node.can_append_child(&child).unwrap() {
@@ -2039,6 +8152,25 @@ mod tests {
         }
     }
 
+    proptest! {
+        #[test]
+        fn test_map_text_identity(ref content in arb_content(10)) {
+            let mut root = parse_document(&format!("# {}\n\n*{}*\n", content, content));
+            let rendered = root.render_commonmark();
+
+            assert_eq!(root.map_text(|text| text.to_string()).unwrap(), 2);
+            assert_eq!(root.render_commonmark(), rendered);
+        }
+    }
+
+    #[test]
+    fn test_map_text() {
+        let mut root = parse_document("# Title\n\nSome *text*\n");
+
+        assert_eq!(root.map_text(|text| text.to_uppercase()).unwrap(), 3);
+        assert_eq!(root.render_commonmark(), "# TITLE\n\nSOME *TEXT*\n");
+    }
+
     proptest! {
         #[test]
         fn test_fence_info_get_set(ref content in arb_content(10)){
@@ -0,0 +1,328 @@
+//! Reconstruction of document trees from the XML produced by `Node::render_xml`
+
+use super::{
+    check_return_code, cmark_node_set_fence_info, cmark_node_set_heading_level,
+    cmark_node_set_list_delim, cmark_node_set_list_start, cmark_node_set_list_tight,
+    cmark_node_set_list_type, cmark_node_set_literal, cmark_node_set_on_enter,
+    cmark_node_set_on_exit, cmark_node_set_title, cmark_node_set_url, DoogieResult, Node,
+    NodeResource,
+};
+use constants::{DelimType, ListType, NodeType};
+use errors::DoogieError;
+use libc::c_int;
+use std::ffi::CString;
+
+/// An element that has been opened but not yet closed
+struct OpenElement {
+    name: String,
+    node: Node,
+    literal: String,
+}
+
+/// Parses the XML representation of a document produced by `Node::render_xml`
+///
+/// libcmark does not provide an XML parser, so this understands only the subset of XML that
+/// libcmark renders: the elements of the CommonMark DTD, their attributes and the predefined and
+/// numeric character references. The `sourcepos` attribute is ignored, so the `Node`s of the
+/// returned tree have no source positions. A `DoogieError::XmlError` error is returned if the
+/// XML is malformed or contains unknown elements.
+///
+/// # Examples
+///
+/// ```
+/// use doogie::{parse_document, parse_xml};
+///
+/// let root = parse_document("# My Great Document");
+/// let copy = parse_xml(&root.render_xml()).unwrap();
+///
+/// assert_eq!(copy.render_commonmark(), root.render_commonmark());
+/// ```
+pub fn parse_xml(buffer: &str) -> DoogieResult<Node> {
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut root = None;
+    let mut pos = 0;
+
+    while let Some(offset) = buffer[pos..].find('<') {
+        let start = pos + offset;
+        push_text(&mut stack, &buffer[pos..start])?;
+
+        let rest = &buffer[start..];
+        let (terminator, skip) = if rest.starts_with("<?") {
+            ("?>", true)
+        } else if rest.starts_with("<!--") {
+            ("-->", true)
+        } else {
+            (">", rest.starts_with("<!"))
+        };
+        let end = match rest.find(terminator) {
+            Some(end) => start + end + terminator.len(),
+            None => return Err(xml_error("unterminated tag")),
+        };
+        pos = end;
+        if skip {
+            continue;
+        }
+
+        let tag = &buffer[start + 1..end - 1];
+        if tag.starts_with('/') {
+            let element = match stack.pop() {
+                Some(element) => element,
+                None => return Err(xml_error("unexpected closing tag")),
+            };
+            if element.name != tag[1..].trim() {
+                return Err(xml_error(&format!("mismatched closing tag {}", tag)));
+            }
+
+            let node = close_element(element)?;
+            if stack.is_empty() {
+                root = Some(node);
+            }
+        } else {
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_right_matches('/');
+            if tag.starts_with(char::is_whitespace) {
+                return Err(xml_error(&format!(
+                    "whitespace before the name of tag {}",
+                    tag
+                )));
+            }
+            let name = tag.split_whitespace().next().unwrap_or("").to_string();
+
+            let mut node = Node::from_type(node_type(&name)?)?;
+            for (key, value) in parse_attributes(&tag[name.len()..])? {
+                set_attribute(&node, &key, &value)?;
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.node.append_child(&mut node)?,
+                None if root.is_none() => node.manager().track_root(&node.pointer()),
+                None => return Err(xml_error("multiple root elements")),
+            }
+
+            let element = OpenElement {
+                name,
+                node,
+                literal: String::new(),
+            };
+            if self_closing {
+                let node = close_element(element)?;
+                if stack.is_empty() {
+                    root = Some(node);
+                }
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+
+    push_text(&mut stack, &buffer[pos..])?;
+    match (root, stack.is_empty()) {
+        (Some(root), true) => Ok(root),
+        (None, _) => Err(xml_error("no root element")),
+        (Some(_), false) => Err(xml_error("unclosed element")),
+    }
+}
+
+/// Returns an `XmlError` with the given reason
+fn xml_error(reason: &str) -> DoogieError {
+    DoogieError::XmlError(reason.to_string())
+}
+
+/// Returns whether the content of the element is the literal of its `Node`
+fn has_literal(name: &str) -> bool {
+    match name {
+        "text" | "code" | "code_block" | "html_block" | "html_inline" => true,
+        _ => false,
+    }
+}
+
+/// Handles the character data between two tags
+///
+/// Character data is kept as the literal of elements that have one. Elsewhere it may only be the
+/// whitespace used to indent the XML.
+fn push_text(stack: &mut Vec<OpenElement>, text: &str) -> DoogieResult<()> {
+    match stack.last_mut() {
+        Some(ref mut element) if has_literal(&element.name) => {
+            element.literal.push_str(&unescape(text)?);
+            Ok(())
+        }
+        _ if text.trim().is_empty() => Ok(()),
+        _ => Err(xml_error(&format!("unexpected text {:?}", text.trim()))),
+    }
+}
+
+/// Finishes an element by setting the literal of its `Node`
+fn close_element(element: OpenElement) -> DoogieResult<Node> {
+    if has_literal(&element.name) {
+        let literal = CString::new(element.literal)?;
+        unsafe {
+            check_return_code(
+                cmark_node_set_literal(element.node.pointer(), literal.as_ptr()),
+                "parse_xml",
+            )?;
+        }
+    }
+
+    Ok(element.node)
+}
+
+/// Returns the `NodeType` corresponding to the name of an element
+fn node_type(name: &str) -> DoogieResult<NodeType> {
+    match name {
+        "document" => Ok(NodeType::CMarkNodeDocument),
+        "block_quote" => Ok(NodeType::CMarkNodeBlockQuote),
+        "list" => Ok(NodeType::CMarkNodeList),
+        "item" => Ok(NodeType::CMarkNodeItem),
+        "code_block" => Ok(NodeType::CMarkNodeCodeBlock),
+        "html_block" => Ok(NodeType::CMarkNodeHtmlBlock),
+        "custom_block" => Ok(NodeType::CMarkNodeCustomBlock),
+        "paragraph" => Ok(NodeType::CMarkNodeParagraph),
+        "heading" => Ok(NodeType::CMarkNodeHeading),
+        "thematic_break" => Ok(NodeType::CMarkNodeThematicBreak),
+        "text" => Ok(NodeType::CMarkNodeText),
+        "softbreak" => Ok(NodeType::CMarkNodeSoftbreak),
+        "linebreak" => Ok(NodeType::CMarkNodeLinebreak),
+        "code" => Ok(NodeType::CMarkNodeCode),
+        "html_inline" => Ok(NodeType::CMarkNodeHtmlInline),
+        "custom_inline" => Ok(NodeType::CMarkNodeCustomInline),
+        "emph" => Ok(NodeType::CMarkNodeEmph),
+        "strong" => Ok(NodeType::CMarkNodeStrong),
+        "link" => Ok(NodeType::CMarkNodeLink),
+        "image" => Ok(NodeType::CMarkNodeImage),
+        _ => Err(xml_error(&format!("unknown element {}", name))),
+    }
+}
+
+/// Parses the `key="value"` attributes of a start tag
+fn parse_attributes(mut text: &str) -> DoogieResult<Vec<(String, String)>> {
+    let mut attributes = Vec::new();
+    loop {
+        text = text.trim_left();
+        if text.is_empty() {
+            return Ok(attributes);
+        }
+
+        let equals = match text.find("=\"") {
+            Some(equals) => equals,
+            None => return Err(xml_error(&format!("malformed attribute {}", text))),
+        };
+        let value_start = equals + 2;
+        let value_end = match text[value_start..].find('"') {
+            Some(length) => value_start + length,
+            None => return Err(xml_error(&format!("unterminated attribute {}", text))),
+        };
+
+        attributes.push((
+            text[..equals].trim().to_string(),
+            unescape(&text[value_start..value_end])?,
+        ));
+        text = &text[value_end + 1..];
+    }
+}
+
+/// Sets an attribute of an element on its `Node`
+fn set_attribute(node: &Node, key: &str, value: &str) -> DoogieResult<()> {
+    let pointer = node.pointer();
+    let op = "parse_xml";
+    let integer = || {
+        value
+            .parse::<c_int>()
+            .map_err(|_| xml_error(&format!("invalid {} {}", key, value)))
+    };
+    let string = || CString::new(value);
+
+    unsafe {
+        match (node.get_cmark_type()?, key) {
+            (NodeType::CMarkNodeHeading, "level") => {
+                check_return_code(cmark_node_set_heading_level(pointer, integer()?), op)
+            }
+            (NodeType::CMarkNodeList, "type") => {
+                let list_type = match value {
+                    "ordered" => ListType::CMarkOrderedList,
+                    _ => ListType::CMarkBulletList,
+                };
+                check_return_code(
+                    cmark_node_set_list_type(pointer, u32::from(list_type) as c_int),
+                    op,
+                )
+            }
+            (NodeType::CMarkNodeList, "start") => {
+                check_return_code(cmark_node_set_list_start(pointer, integer()?), op)
+            }
+            (NodeType::CMarkNodeList, "delim") => {
+                let delim = match value {
+                    "paren" => DelimType::CMarkParenDelim,
+                    _ => DelimType::CMarkPeriodDelim,
+                };
+                check_return_code(
+                    cmark_node_set_list_delim(pointer, u32::from(delim) as c_int),
+                    op,
+                )
+            }
+            (NodeType::CMarkNodeList, "tight") => check_return_code(
+                cmark_node_set_list_tight(pointer, (value == "true") as c_int),
+                op,
+            ),
+            (NodeType::CMarkNodeCodeBlock, "info") => {
+                check_return_code(cmark_node_set_fence_info(pointer, string()?.as_ptr()), op)
+            }
+            (NodeType::CMarkNodeLink, "destination")
+            | (NodeType::CMarkNodeImage, "destination") => {
+                check_return_code(cmark_node_set_url(pointer, string()?.as_ptr()), op)
+            }
+            (NodeType::CMarkNodeLink, "title") | (NodeType::CMarkNodeImage, "title") => {
+                check_return_code(cmark_node_set_title(pointer, string()?.as_ptr()), op)
+            }
+            (NodeType::CMarkNodeCustomBlock, "on_enter")
+            | (NodeType::CMarkNodeCustomInline, "on_enter") => {
+                check_return_code(cmark_node_set_on_enter(pointer, string()?.as_ptr()), op)
+            }
+            (NodeType::CMarkNodeCustomBlock, "on_exit")
+            | (NodeType::CMarkNodeCustomInline, "on_exit") => {
+                check_return_code(cmark_node_set_on_exit(pointer, string()?.as_ptr()), op)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replaces the character references in XML character data with the characters they represent
+fn unescape(text: &str) -> DoogieResult<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(ampersand) = rest.find('&') {
+        result.push_str(&rest[..ampersand]);
+        rest = &rest[ampersand..];
+
+        let semicolon = match rest.find(';') {
+            Some(semicolon) => semicolon,
+            None => return Err(xml_error(&format!("unterminated reference {}", rest))),
+        };
+        let reference = &rest[1..semicolon];
+        let character = match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if reference.starts_with("#x") || reference.starts_with("#X") => {
+                u32::from_str_radix(&reference[2..], 16)
+                    .ok()
+                    .and_then(::std::char::from_u32)
+            }
+            _ if reference.starts_with('#') => reference[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(::std::char::from_u32),
+            _ => None,
+        };
+
+        match character {
+            Some(character) => result.push(character),
+            None => return Err(xml_error(&format!("unknown reference &{};", reference))),
+        }
+        rest = &rest[semicolon + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}